@@ -0,0 +1,235 @@
+// Bookmarks, stored one per line in a tab-separated file under the XDG data
+// directory, with import and export in the Netscape bookmark file format
+// understood by every other browser.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use scraper::{Html, Selector};
+
+use crate::document::escape_html;
+use crate::keys::{Action, Keymap};
+use crate::paths;
+use crate::util::{clean, now};
+
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    // Seconds since the Unix epoch
+    pub added: u64,
+}
+
+impl Bookmark {
+    fn matches(&self, query: &str, tag: Option<&str>) -> bool {
+        if let Some(tag) = tag {
+            if !self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                return false;
+            }
+        }
+        let haystack = format!("{} {} {}", self.title, self.url, self.tags.join(" ")).to_lowercase();
+        query.split_whitespace().all(|word| haystack.contains(&word.to_lowercase()))
+    }
+}
+
+pub const PAGE_URL: &str = "about:bookmarks";
+
+// The bookmarks page filtered by `query`
+pub fn page_url(query: &str) -> String {
+    if query.trim().is_empty() {
+        return PAGE_URL.to_string();
+    }
+    let encoded: String = url::form_urlencoded::byte_serialize(query.trim().as_bytes()).collect();
+    format!("{}?q={}", PAGE_URL, encoded)
+}
+
+// Split a comma-separated tag list, dropping empty entries
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    tags.split(',').map(clean).filter(|t| !t.is_empty()).collect()
+}
+
+#[derive(Default)]
+pub struct Bookmarks {
+    pub entries: Vec<Bookmark>,
+    // Where changes are saved; None keeps bookmarks in memory only
+    path: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("bookmarks.tsv"))
+    }
+
+    // Load bookmarks from `path`; a missing file is an empty list
+    pub fn load(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut bookmarks = Bookmarks { entries: Vec::new(), path };
+        let contents = match &bookmarks.path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(bookmarks),
+                Err(e) => return Err(e),
+            },
+            None => return Ok(bookmarks),
+        };
+
+        for line in contents.lines() {
+            let mut fields = line.split('\t');
+            let Some(url) = fields.next().filter(|u| !u.is_empty()) else { continue };
+            bookmarks.entries.push(Bookmark {
+                url: url.to_string(),
+                title: fields.next().unwrap_or("").to_string(),
+                tags: parse_tags(fields.next().unwrap_or("")),
+                added: fields.next().and_then(|a| a.parse().ok()).unwrap_or(0),
+            });
+        }
+        Ok(bookmarks)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for b in &self.entries {
+            contents.push_str(&format!("{}\t{}\t{}\t{}\n", clean(&b.url), clean(&b.title), b.tags.join(","), b.added));
+        }
+        // Write to a temporary file first so a crash can't truncate the bookmarks
+        let temporary = path.with_extension("tsv.tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Bookmark> {
+        self.entries.iter().find(|b| b.url == url)
+    }
+
+    // Add a bookmark, or update the title and tags of an existing one
+    pub fn add(&mut self, url: &str, title: &str, tags: Vec<String>) {
+        match self.entries.iter_mut().find(|b| b.url == url) {
+            Some(existing) => {
+                existing.title = clean(title);
+                existing.tags = tags;
+            }
+            None => self.entries.push(Bookmark { url: clean(url), title: clean(title), tags, added: now() }),
+        }
+    }
+
+    pub fn remove(&mut self, url: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|b| b.url != url);
+        self.entries.len() != before
+    }
+
+    // Merge bookmarks from a Netscape bookmark file; returns how many were new
+    pub fn import_netscape(&mut self, html: &str) -> usize {
+        let document = Html::parse_document(html);
+        let selector = Selector::parse("a[href]").unwrap();
+        let mut imported = 0;
+
+        for a in document.select(&selector) {
+            let url = a.value().attr("href").unwrap_or("");
+            // Firefox smart folders and bookmarklets aren't pages
+            if url.starts_with("place:") || url.starts_with("javascript:") || self.get(url).is_some() {
+                continue;
+            }
+            let title = a.text().collect::<String>();
+            self.entries.push(Bookmark {
+                url: clean(url),
+                title: clean(&title),
+                tags: parse_tags(a.value().attr("tags").unwrap_or("")),
+                added: a.value().attr("add_date").and_then(|d| d.parse().ok()).unwrap_or_else(now),
+            });
+            imported += 1;
+        }
+        imported
+    }
+
+    pub fn export_netscape(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n",
+        );
+        for b in &self.entries {
+            html.push_str(&format!(
+                "    <DT><A HREF=\"{}\" ADD_DATE=\"{}\" TAGS=\"{}\">{}</A>\n",
+                escape_html(&b.url),
+                b.added,
+                escape_html(&b.tags.join(",")),
+                escape_html(&b.title),
+            ));
+        }
+        html.push_str("</DL><p>\n");
+        html
+    }
+
+    // The about:bookmarks page, optionally filtered by words and a tag
+    pub fn to_html(&self, query: &str, tag: Option<&str>, keymap: &Keymap) -> String {
+        let mut html = String::from("<html><head><title>Bookmarks</title></head><body><h1>Bookmarks</h1>");
+
+        if !query.is_empty() || tag.is_some() {
+            let mut filters = Vec::new();
+            if !query.is_empty() {
+                filters.push(format!("matching <b>{}</b>", escape_html(query)));
+            }
+            if let Some(tag) = tag {
+                filters.push(format!("tagged <b>{}</b>", escape_html(tag)));
+            }
+            html.push_str(&format!("<p>Showing bookmarks {}. <a href=\"about:bookmarks\">Show all</a></p>", filters.join(" and ")));
+        }
+
+        let mut tags: Vec<(&str, usize)> = Vec::new();
+        for t in self.entries.iter().flat_map(|b| &b.tags) {
+            match tags.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(t)) {
+                Some((_, count)) => *count += 1,
+                None => tags.push((t, 1)),
+            }
+        }
+        if !tags.is_empty() {
+            tags.sort_by_key(|(name, _)| name.to_lowercase());
+            html.push_str("<p>Tags:");
+            for (name, count) in tags {
+                let encoded: String = url::form_urlencoded::byte_serialize(name.as_bytes()).collect();
+                html.push_str(&format!(" <a href=\"about:bookmarks?tag={}\">{}</a> ({})", encoded, escape_html(name), count));
+            }
+            html.push_str("</p>");
+        }
+
+        let shown: Vec<&Bookmark> = self.entries.iter().filter(|b| b.matches(query, tag)).collect();
+        if shown.is_empty() {
+            html.push_str("<p>No bookmarks.</p>");
+        } else {
+            html.push_str("<ul>");
+            for b in shown {
+                let title = if b.title.is_empty() { &b.url } else { &b.title };
+                html.push_str(&format!("<li><a href=\"{}\">{}</a><br><code>{}</code>", escape_html(&b.url), escape_html(title), escape_html(&b.url)));
+                if !b.tags.is_empty() {
+                    html.push_str(&format!(" <i>[{}]</i>", escape_html(&b.tags.join(", "))));
+                }
+                html.push_str("</li>");
+            }
+            html.push_str("</ul>");
+        }
+
+        html.push_str(&format!(
+            "<p><small>{}: bookmark the current page &middot; {}: edit the selected bookmark &middot; \
+             {}: delete the selected bookmark &middot; {}: filter</small></p></body></html>",
+            escape_html(&keymap.keys_for(Action::Bookmark)),
+            escape_html(&keymap.keys_for(Action::EditBookmark)),
+            escape_html(&keymap.keys_for(Action::DeleteBookmark)),
+            escape_html(&keymap.keys_for(Action::Filter)),
+        ));
+        html
+    }
+}
+
+pub fn import_file(bookmarks: &mut Bookmarks, path: &Path) -> io::Result<usize> {
+    let html = fs::read_to_string(path)?;
+    let imported = bookmarks.import_netscape(&html);
+    bookmarks.save()?;
+    Ok(imported)
+}
@@ -0,0 +1,180 @@
+// Command-line argument parsing.
+
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::config::{self, DEFAULT_USER_AGENT};
+
+pub const DEFAULT_DUMP_WIDTH: usize = 80;
+
+// Settings left unset here come from the config file
+pub struct Options {
+    pub url: Option<String>,
+    pub dump: bool,
+    pub user_agent: Option<String>,
+    pub width: usize,
+    pub config: Option<PathBuf>,
+}
+
+// What main should do after parsing the arguments
+pub enum Command {
+    Run(Options),
+    ImportBookmarks(PathBuf),
+    // None writes to stdout
+    ExportBookmarks(Option<PathBuf>),
+    Help,
+    Version,
+}
+
+pub fn usage() -> String {
+    format!(
+        "Usage: {name} [OPTIONS] [URL]
+
+Browse the web from the terminal. URL may be a full URL, a bare hostname
+(https:// is assumed) or a path to a local file. Without one, the homepage
+from the config file is opened.
+
+Options:
+  -d, --dump              Render the page to stdout with a numbered list
+                          of link references and exit
+  -w, --width <COLUMNS>   Wrap width for --dump output (default: {width})
+  -A, --user-agent <UA>   User-Agent header to send (default: {ua})
+  -c, --config <FILE>     Read settings from FILE instead of {config}
+      --import-bookmarks <FILE>
+                          Merge bookmarks from a Netscape bookmark file and exit
+      --export-bookmarks <FILE>
+                          Write bookmarks as a Netscape bookmark file (- for
+                          stdout) and exit
+  -h, --help              Print this help and exit
+  -V, --version           Print version information and exit",
+        name = env!("CARGO_PKG_NAME"),
+        width = DEFAULT_DUMP_WIDTH,
+        ua = DEFAULT_USER_AGENT,
+        config = config::default_path().map_or("~/.config/connex/config.toml".to_string(), |p| p.display().to_string()),
+    )
+}
+
+pub fn version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut url = None;
+    let mut dump = false;
+    let mut user_agent = None;
+    let mut width = DEFAULT_DUMP_WIDTH;
+    let mut config = None;
+
+    while let Some(arg) = args.next() {
+        // Support both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value_for = |name: &str| -> Result<String, String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-d" | "--dump" => dump = true,
+            "-A" | "--user-agent" => user_agent = Some(value_for(&flag)?),
+            "-c" | "--config" => config = Some(PathBuf::from(value_for(&flag)?)),
+            "--import-bookmarks" => return Ok(Command::ImportBookmarks(PathBuf::from(value_for(&flag)?))),
+            "--export-bookmarks" => {
+                let path = value_for(&flag)?;
+                return Ok(Command::ExportBookmarks((path != "-").then(|| PathBuf::from(path))));
+            }
+            "-w" | "--width" => {
+                let value = value_for(&flag)?;
+                width = match value.parse::<usize>() {
+                    Ok(w) if w > 0 => w,
+                    _ => return Err(format!("invalid width: {}", value)),
+                };
+            }
+            "--" => {
+                if let Some(rest) = args.next() {
+                    url = Some(rest);
+                }
+            }
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option: {}", flag));
+            }
+            _ => {
+                if url.is_some() {
+                    return Err(format!("unexpected argument: {}", arg));
+                }
+                url = Some(arg);
+            }
+        }
+    }
+
+    let url = url.map(|target| resolve_target(&target)).transpose()?;
+    Ok(Command::Run(Options { url, dump, user_agent, width, config }))
+}
+
+// Turn whatever the user typed into a URL we can fetch: full URLs pass
+// through, existing local paths become file:// URLs and anything else is
+// treated as a hostname.
+pub fn resolve_target(target: &str) -> Result<String, String> {
+    if let Ok(url) = Url::parse(target) {
+        // A single-letter scheme is a Windows drive letter, not a URL
+        if url.scheme().len() > 1 && (url.has_host() || matches!(url.scheme(), "file" | "about")) {
+            return Ok(url.to_string());
+        }
+    }
+
+    let path = Path::new(target);
+    if path.exists() {
+        let absolute = path
+            .canonicalize()
+            .map_err(|e| format!("cannot open {}: {}", target, e))?;
+        return Url::from_file_path(&absolute)
+            .map(|u| u.to_string())
+            .map_err(|_| format!("cannot open {}", target));
+    }
+    if target.starts_with('/') || target.starts_with("./") || target.starts_with("../") {
+        return Err(format!("no such file: {}", target));
+    }
+
+    Url::parse(&format!("https://{}", target))
+        .map(|u| u.to_string())
+        .map_err(|e| format!("invalid URL {}: {}", target, e))
+}
+
+// Interpret text typed into the address bar: URLs and hostnames are opened
+// directly, anything else becomes a search query.
+pub fn resolve_address(input: &str, search_url: &str) -> String {
+    let input = input.trim();
+
+    // Local files, as on the command line
+    if input.starts_with('/') || input.starts_with("./") || input.starts_with("../") {
+        if let Ok(url) = resolve_target(input) {
+            return url;
+        }
+    }
+
+    if !input.contains(char::is_whitespace) {
+        if let Ok(url) = Url::parse(input) {
+            if url.has_host() || matches!(url.scheme(), "file" | "about") {
+                return url.to_string();
+            }
+        }
+
+        let host = input.split(['/', ':', '?', '#']).next().unwrap_or("");
+        let looks_like_host = host == "localhost"
+            || (host.contains('.') && !host.starts_with('.') && !host.ends_with('.'));
+        if looks_like_host {
+            if let Ok(url) = Url::parse(&format!("https://{}", input)) {
+                return url.to_string();
+            }
+        }
+    }
+
+    let query: String = url::form_urlencoded::byte_serialize(input.as_bytes()).collect();
+    search_url.replace("%s", &query)
+}
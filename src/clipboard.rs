@@ -0,0 +1,14 @@
+// Copying text to the system clipboard with the OSC 52 escape sequence,
+// which most terminals support and which also works over SSH. Inside tmux
+// it needs `set-clipboard on`.
+
+use std::io::{self, Write};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()
+}
@@ -0,0 +1,473 @@
+// Settings from ~/.config/connex/config.toml. Every key is optional; a
+// missing file means the defaults below. A commented example:
+//
+//     homepage = "https://example.com"
+//     user_agent = "Mozilla/5.0"
+//     accept_language = "en-GB, en;q=0.8"
+//     # duckduckgo, google, bing, wikipedia or a URL with %s for the query
+//     search_engine = "duckduckgo"
+//     # Characters for link hint labels
+//     hint_chars = "asdfghjkl"
+//     # Show [n] before every link, as in --dump output
+//     number_links = false
+//
+//     [scroll]
+//     line = 1
+//     page = 10
+//     horizontal = 8
+//
+//     [network]
+//     # Seconds; 0 waits forever. The read timeout applies to the response
+//     # starting and then to each chunk of it.
+//     connect_timeout = 10
+//     read_timeout = 60
+//     max_redirects = 10
+//     # http://, https://, socks5:// or socks5h:// (names resolved by the proxy)
+//     proxy = "socks5h://localhost:9050"
+//
+//     [network.headers]
+//     # Extra headers for a domain and its subdomains, or "*" for all sites
+//     "example.com" = { "X-Api-Key" = "secret" }
+//     "*" = { "DNT" = "1" }
+//
+//     [cookies]
+//     # Whether sites not listed below may set cookies
+//     accept = true
+//     # Domains include their subdomains; the most specific entry wins
+//     allow = ["example.com"]
+//     block = ["ads.example.com"]
+//
+//     [colors]
+//     # Colors and modifiers, with "on" before the background color
+//     link = "blue underlined"
+//     selected_link = "white on blue bold"
+//
+//     [keys]
+//     # default, vi or emacs
+//     preset = "vi"
+//
+//     [keys.bindings]
+//     # Action names are listed on the about:keys page; "none" unbinds
+//     "<C-o>" = "back"
+//     "x" = "none"
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+use crate::cli;
+use crate::cookies::CookiePolicy;
+use crate::http::FetchOptions;
+use crate::keys::{Action, Keymap, PRESETS};
+use crate::paths;
+
+pub const DEFAULT_START_URL: &str = "https://en.wikipedia.org/wiki/Main_Page";
+pub const DEFAULT_USER_AGENT: &str = concat!("connex/", env!("CARGO_PKG_VERSION"));
+// `%s` is replaced by the URL-encoded query
+pub const DEFAULT_SEARCH_URL: &str = "https://html.duckduckgo.com/html/?q=%s";
+
+const SEARCH_ENGINES: &[(&str, &str)] = &[
+    ("duckduckgo", DEFAULT_SEARCH_URL),
+    ("google", "https://www.google.com/search?q=%s"),
+    ("bing", "https://www.bing.com/search?q=%s"),
+    ("wikipedia", "https://en.wikipedia.org/w/index.php?search=%s"),
+];
+
+// The file as written, before validation
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    homepage: Option<String>,
+    user_agent: Option<String>,
+    accept_language: Option<String>,
+    search_engine: Option<String>,
+    hint_chars: Option<String>,
+    number_links: Option<bool>,
+    scroll: ScrollFile,
+    network: NetworkFile,
+    cookies: CookiesFile,
+    colors: ColorsFile,
+    keys: KeysFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, expecting = "a [scroll] table")]
+struct ScrollFile {
+    line: Option<u16>,
+    page: Option<u16>,
+    horizontal: Option<u16>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, expecting = "a [network] table")]
+struct NetworkFile {
+    connect_timeout: Option<u64>,
+    // Called timeout before reads were timed separately
+    #[serde(alias = "timeout")]
+    read_timeout: Option<u64>,
+    max_redirects: Option<usize>,
+    proxy: Option<String>,
+    headers: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, expecting = "a [cookies] table")]
+struct CookiesFile {
+    accept: Option<bool>,
+    allow: Vec<String>,
+    block: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, expecting = "a [colors] table")]
+struct ColorsFile {
+    link: Option<String>,
+    selected_link: Option<String>,
+    active_tab: Option<String>,
+    search_match: Option<String>,
+    current_match: Option<String>,
+    hint: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, expecting = "a [keys] table")]
+struct KeysFile {
+    preset: Option<String>,
+    bindings: BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub link: Style,
+    pub selected_link: Style,
+    pub active_tab: Style,
+    pub search_match: Style,
+    pub current_match: Style,
+    pub hint: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            link: Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+            selected_link: Style::default().bg(Color::Blue).fg(Color::White).add_modifier(Modifier::BOLD),
+            active_tab: Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+            search_match: Style::default().fg(Color::Black).bg(Color::Yellow),
+            current_match: Style::default().fg(Color::Black).bg(Color::LightRed).add_modifier(Modifier::BOLD),
+            hint: Style::default().fg(Color::Black).bg(Color::LightYellow).add_modifier(Modifier::BOLD),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub homepage: String,
+    pub user_agent: String,
+    pub accept_language: Option<String>,
+    pub search_url: String,
+    pub hint_chars: String,
+    pub number_links: bool,
+    // Lines for the arrow keys and PageUp/PageDown, columns for Left/Right
+    pub scroll_line: u16,
+    pub scroll_page: u16,
+    pub scroll_horizontal: u16,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub max_redirects: usize,
+    pub proxy: Option<String>,
+    // Extra request headers by domain
+    pub headers: Vec<(String, HeaderMap)>,
+    pub cookies: CookiePolicy,
+    pub theme: Theme,
+    pub keymap: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            homepage: DEFAULT_START_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            accept_language: None,
+            search_url: DEFAULT_SEARCH_URL.to_string(),
+            hint_chars: "asdfghjkl".to_string(),
+            number_links: false,
+            scroll_line: 1,
+            scroll_page: 10,
+            scroll_horizontal: 8,
+            connect_timeout: Some(Duration::from_secs(10)),
+            read_timeout: None,
+            max_redirects: 10,
+            proxy: None,
+            headers: Vec::new(),
+            cookies: CookiePolicy::default(),
+            theme: Theme::default(),
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn fetch_options(&self) -> FetchOptions {
+        FetchOptions {
+            user_agent: self.user_agent.clone(),
+            accept_language: self.accept_language.clone(),
+            headers: self.headers.clone(),
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            max_redirects: self.max_redirects,
+            proxy: self.proxy.clone(),
+        }
+    }
+}
+
+pub struct ConfigError {
+    path: PathBuf,
+    message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// Read the config file named on the command line, or the default one if it
+// exists
+pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    let error = |message: String| ConfigError { path: path.clone(), message };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => return Err(error(e.to_string())),
+    };
+    // toml's messages quote the offending line; keep them, minus the
+    // trailing newline
+    let file: ConfigFile = toml::from_str(&contents).map_err(|e| error(e.to_string().trim_end().to_string()))?;
+    validate(file).map_err(error)
+}
+
+fn validate(file: ConfigFile) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    if let Some(homepage) = file.homepage {
+        config.homepage = cli::resolve_target(&homepage).map_err(|e| format!("homepage: {}", e))?;
+    }
+    if let Some(user_agent) = file.user_agent {
+        if user_agent.trim().is_empty() || user_agent.contains(['\r', '\n']) {
+            return Err("user_agent: must be a single non-empty line".to_string());
+        }
+        config.user_agent = user_agent;
+    }
+    if let Some(language) = file.accept_language {
+        if HeaderValue::from_str(&language).is_err() || language.trim().is_empty() {
+            return Err("accept_language: must be a single non-empty line, such as \"en-GB, en;q=0.8\"".to_string());
+        }
+        config.accept_language = Some(language);
+    }
+    if let Some(engine) = file.search_engine {
+        config.search_url = match SEARCH_ENGINES.iter().find(|(name, _)| name.eq_ignore_ascii_case(&engine)) {
+            Some((_, url)) => url.to_string(),
+            None if engine.contains("%s") && url::Url::parse(&engine.replace("%s", "")).is_ok() => engine,
+            None => {
+                let names: Vec<&str> = SEARCH_ENGINES.iter().map(|(name, _)| *name).collect();
+                return Err(format!(
+                    "search_engine: expected one of {} or a URL containing %s, got \"{}\"",
+                    names.join(", "),
+                    engine
+                ));
+            }
+        };
+    }
+
+    if let Some(chars) = file.hint_chars {
+        let mut unique: Vec<char> = chars.chars().collect();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() < 2 || unique.len() != chars.chars().count() || unique.iter().any(|c| c.is_whitespace()) {
+            return Err("hint_chars: needs at least two different characters, without repeats or spaces".to_string());
+        }
+        config.hint_chars = chars;
+    }
+
+    if let Some(number_links) = file.number_links {
+        config.number_links = number_links;
+    }
+
+    for (name, value, field) in [
+        ("scroll.line", file.scroll.line, &mut config.scroll_line),
+        ("scroll.page", file.scroll.page, &mut config.scroll_page),
+        ("scroll.horizontal", file.scroll.horizontal, &mut config.scroll_horizontal),
+    ] {
+        match value {
+            Some(0) => return Err(format!("{}: must be at least 1", name)),
+            Some(value) => *field = value,
+            None => {}
+        }
+    }
+
+    // Zero turns a timeout off
+    let seconds = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+    if let Some(s) = file.network.connect_timeout {
+        config.connect_timeout = seconds(s);
+    }
+    if let Some(s) = file.network.read_timeout {
+        config.read_timeout = seconds(s);
+    }
+    if let Some(max) = file.network.max_redirects {
+        config.max_redirects = max;
+    }
+    if let Some(proxy) = file.network.proxy {
+        let scheme = url::Url::parse(&proxy).map(|url| url.scheme().to_string()).unwrap_or_default();
+        if !["http", "https", "socks5", "socks5h"].contains(&scheme.as_str()) {
+            return Err(format!(
+                "network.proxy: expected a URL starting with http://, https://, socks5:// or socks5h://, got \"{}\"",
+                proxy
+            ));
+        }
+        config.proxy = Some(proxy);
+    }
+    for (domain, headers) in file.network.headers {
+        let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+        if domain.is_empty() || domain.contains(|c: char| c.is_whitespace() || c == '/') {
+            return Err(format!("network.headers: expected domain names such as \"example.com\" or \"*\", got \"{}\"", domain));
+        }
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("network.headers.\"{}\": invalid header name \"{}\"", domain, name))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|_| format!("network.headers.\"{}\": invalid value for {}", domain, name))?;
+            map.insert(name, value);
+        }
+        config.headers.push((domain, map));
+    }
+
+    if let Some(accept) = file.cookies.accept {
+        config.cookies.accept = accept;
+    }
+    for (name, domains, field) in [
+        ("cookies.allow", file.cookies.allow, &mut config.cookies.allow),
+        ("cookies.block", file.cookies.block, &mut config.cookies.block),
+    ] {
+        for domain in domains {
+            let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+            if domain.is_empty() || domain.contains(|c: char| c.is_whitespace() || c == '/') {
+                return Err(format!("{}: expected domain names such as \"example.com\"", name));
+            }
+            field.push(domain);
+        }
+    }
+
+    let theme = &mut config.theme;
+    for (name, value, field) in [
+        ("colors.link", file.colors.link, &mut theme.link),
+        ("colors.selected_link", file.colors.selected_link, &mut theme.selected_link),
+        ("colors.active_tab", file.colors.active_tab, &mut theme.active_tab),
+        ("colors.search_match", file.colors.search_match, &mut theme.search_match),
+        ("colors.current_match", file.colors.current_match, &mut theme.current_match),
+        ("colors.hint", file.colors.hint, &mut theme.hint),
+    ] {
+        if let Some(value) = value {
+            *field = parse_style(&value).map_err(|e| format!("{}: {}", name, e))?;
+        }
+    }
+
+    if let Some(preset) = file.keys.preset {
+        config.keymap = Keymap::preset(&preset).ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            format!("keys.preset: expected one of {}, got \"{}\"", names.join(", "), preset)
+        })?;
+    }
+    for (keys, name) in file.keys.bindings {
+        let action = match name.as_str() {
+            "none" => None,
+            _ => Some(Action::from_name(&name).ok_or_else(|| {
+                format!("keys.bindings: unknown action \"{}\" for \"{}\" (see about:keys)", name, keys)
+            })?),
+        };
+        config.keymap.bind(&keys, action).map_err(|e| format!("keys.bindings: {}", e))?;
+    }
+
+    Ok(config)
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    let color = match name.to_lowercase().replace(['_', '-'], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex if hex.len() == 7 && hex.starts_with('#') && hex[1..].chars().all(|c| c.is_ascii_hexdigit()) => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Color::Rgb(channel(1)?, channel(3)?, channel(5)?)
+        }
+        index => Color::Indexed(index.parse().ok()?),
+    };
+    Some(color)
+}
+
+fn parse_modifier(name: &str) -> Option<Modifier> {
+    let modifier = match name.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underline" | "underlined" => Modifier::UNDERLINED,
+        "reverse" | "reversed" => Modifier::REVERSED,
+        "strikethrough" | "crossed_out" => Modifier::CROSSED_OUT,
+        "blink" => Modifier::SLOW_BLINK,
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+// Parse e.g. "bold yellow on #202020": modifiers, a foreground color, and a
+// background color after "on"
+fn parse_style(spec: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    let mut words = spec.split_whitespace();
+    while let Some(word) = words.next() {
+        if word.eq_ignore_ascii_case("on") {
+            let background = words.next().ok_or("expected a color after \"on\"")?;
+            style = style.bg(parse_color(background).ok_or_else(|| format!("unknown color \"{}\"", background))?);
+        } else if let Some(modifier) = parse_modifier(word) {
+            style = style.add_modifier(modifier);
+        } else if let Some(color) = parse_color(word) {
+            style = style.fg(color);
+        } else {
+            return Err(format!("unknown color or modifier \"{}\"", word));
+        }
+    }
+    Ok(style)
+}
@@ -0,0 +1,248 @@
+// What to make of a response: HTML to parse, text to show as it is, or a
+// file that can't be shown, going by its Content-Type. Bytes become text in
+// the charset the headers or the page itself name.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use percent_encoding::percent_decode_str;
+use regex::bytes::Regex;
+use url::Url;
+
+use crate::document::{escape_html, parse_html, Document};
+use crate::http::Response;
+use crate::keys::{Action, Keymap};
+use crate::loader::format_bytes;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Html,
+    Text,
+    Binary,
+}
+
+// "text/html" from "text/html; charset=utf-8"
+pub fn mime_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase()
+}
+
+// Charset named in a Content-Type header value, if any
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+    })
+}
+
+pub fn kind(content_type: &str, bytes: &[u8]) -> ContentKind {
+    let mime = mime_type(content_type);
+    match mime.as_str() {
+        "text/html" | "application/xhtml+xml" => ContentKind::Html,
+        "application/json" | "application/javascript" | "application/xml" => ContentKind::Text,
+        _ if mime.starts_with("text/") || mime.ends_with("+xml") || mime.ends_with("+json") => ContentKind::Text,
+        // Servers that don't say, and local files of unknown types, get a
+        // look at the start of the file
+        "" => sniff(bytes),
+        _ => ContentKind::Binary,
+    }
+}
+
+fn sniff(bytes: &[u8]) -> ContentKind {
+    let start = &bytes[..bytes.len().min(1024)];
+    let text = String::from_utf8_lossy(start).trim_start_matches(['\u{feff}', ' ', '\t', '\r', '\n']).to_lowercase();
+    if ["<!doctype html", "<html", "<head", "<body"].iter().any(|tag| text.starts_with(tag)) {
+        ContentKind::Html
+    } else if Encoding::for_bom(start).is_some() || !start.iter().any(|&b| b < 0x20 && !b"\t\n\x0c\r\x1b".contains(&b)) {
+        ContentKind::Text
+    } else {
+        ContentKind::Binary
+    }
+}
+
+static META_CHARSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap());
+
+// The charset from a <meta charset> or <meta http-equiv="Content-Type">
+// near the start of a page, as browsers look for it
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let label = META_CHARSET.captures(&bytes[..bytes.len().min(1024)])?.get(1)?;
+    // A page in ASCII-compatible bytes can't really be UTF-16
+    match Encoding::for_label(label.as_bytes())? {
+        encoding if encoding == UTF_16LE || encoding == UTF_16BE => Some(UTF_8),
+        encoding => Some(encoding),
+    }
+}
+
+// Decode a response body: a byte order mark wins, then the header, then for
+// HTML a <meta> tag. Without any of them, bytes that aren't UTF-8 are taken
+// to be windows-1252, as in most older Western pages.
+pub fn decode(bytes: &[u8], content_type: &str, html: bool) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| charset(content_type).and_then(|label| Encoding::for_label(label.as_bytes())))
+        .or_else(|| if html { meta_charset(bytes) } else { None })
+        .unwrap_or(if std::str::from_utf8(bytes).is_ok() { UTF_8 } else { WINDOWS_1252 });
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+// The last part of a URL's path, to save it under
+pub fn file_name(url: &str) -> String {
+    let parsed = Url::parse(url).ok();
+    let segment = parsed
+        .as_ref()
+        .and_then(|u| u.path_segments())
+        .and_then(|mut segments| segments.next_back())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .filter(|s| !s.is_empty() && s != "." && s != "..");
+    segment
+        .or_else(|| parsed.as_ref().and_then(|u| u.host_str()).map(str::to_string))
+        .unwrap_or_else(|| "download".to_string())
+        .replace(['/', '\\'], "_")
+}
+
+// The file name in a Content-Disposition header, preferring the UTF-8
+// filename* form to the plain one
+pub fn disposition_file_name(disposition: &str) -> Option<String> {
+    let params: Vec<(String, &str)> = disposition
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+        .collect();
+    let extended = params.iter().find(|(name, _)| name == "filename*").and_then(|(_, value)| {
+        let (charset, rest) = value.split_once('\'')?;
+        let (_, encoded) = rest.split_once('\'')?;
+        let decoded = percent_decode_str(encoded);
+        match charset.to_ascii_lowercase().as_str() {
+            "utf-8" => Some(decoded.decode_utf8_lossy().into_owned()),
+            _ => Some(WINDOWS_1252.decode(&decoded.collect::<Vec<u8>>()).0.into_owned()),
+        }
+    });
+    let plain = || params.iter().find(|(name, _)| name == "filename").map(|(_, value)| value.trim_matches('"').to_string());
+    // Only the name: a path in it must not place the file elsewhere
+    extended
+        .or_else(plain)
+        .map(|name| name.rsplit(['/', '\\']).next().unwrap_or("").to_string())
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+}
+
+// Write `bytes` to a new file at `path`, never over an existing one
+pub fn save(bytes: &[u8], path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(bytes)
+}
+
+// Page shown in place of a file that can't be displayed
+fn info_page(response: &Response, keymap: &Keymap) -> String {
+    let name = file_name(&response.url);
+    let mime = match mime_type(&response.content_type) {
+        mime if mime.is_empty() => "unknown".to_string(),
+        mime => mime,
+    };
+    format!(
+        "<html><head><title>{name}</title></head><body><h1>{name}</h1>\
+         <p>This is a file of type <code>{mime}</code>, {size}, which connex can't show.</p>\
+         <p>{keys}: save it to a file</p><p>{url}</p></body></html>",
+        name = escape_html(&name),
+        mime = escape_html(&mime),
+        size = format_bytes(response.bytes.len() as u64),
+        keys = escape_html(&keymap.keys_for(Action::SavePage)),
+        url = escape_html(&response.url),
+    )
+}
+
+// Lay out a response as a page, whatever its type
+pub fn render(response: &Response, keymap: &Keymap) -> Result<Document, String> {
+    let base_url = Url::parse(&response.url).map_err(|e| e.to_string())?;
+    let document = match kind(&response.content_type, &response.bytes) {
+        ContentKind::Html => parse_html(&decode(&response.bytes, &response.content_type, true), &base_url),
+        ContentKind::Text => Document::from_plain_text(&decode(&response.bytes, &response.content_type, false)),
+        ContentKind::Binary => parse_html(&info_page(response, keymap), &base_url),
+    };
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_by_content_type() {
+        assert!(kind("text/html; charset=utf-8", b"") == ContentKind::Html);
+        assert!(kind("Application/XHTML+XML", b"") == ContentKind::Html);
+        assert!(kind("text/plain", b"<html>") == ContentKind::Text);
+        assert!(kind("text/csv", b"") == ContentKind::Text);
+        assert!(kind("application/json", b"") == ContentKind::Text);
+        assert!(kind("image/svg+xml", b"") == ContentKind::Text);
+        assert!(kind("application/ld+json", b"") == ContentKind::Text);
+        assert!(kind("application/pdf", b"%PDF") == ContentKind::Binary);
+        assert!(kind("image/png", b"") == ContentKind::Binary);
+    }
+
+    #[test]
+    fn sniffing_without_a_content_type() {
+        assert!(kind("", b"\xef\xbb\xbf  <!DOCTYPE html><p>hi") == ContentKind::Html);
+        assert!(kind("", b"\n<HTML>") == ContentKind::Html);
+        assert!(kind("", b"just some text\r\n\twith tabs\n") == ContentKind::Text);
+        assert!(kind("", "plain UTF-8: \u{e9}".as_bytes()) == ContentKind::Text);
+        assert!(kind("", b"\xff\xfeh\x00i\x00") == ContentKind::Text);
+        assert!(kind("", b"\x89PNG\r\n\x1a\n\x00\x00") == ContentKind::Binary);
+        assert!(kind("", b"PK\x03\x04") == ContentKind::Binary);
+    }
+
+    #[test]
+    fn decoding() {
+        // A byte order mark wins over the header
+        assert_eq!(decode(b"\xef\xbb\xbfcaf\xc3\xa9", "text/plain; charset=iso-8859-1", false), "caf\u{e9}");
+        assert_eq!(decode(b"\xff\xfeh\x00i\x00", "", false), "hi");
+        // Then the header, quoted or not
+        assert_eq!(decode(b"caf\xe9", "text/plain; charset=\"ISO-8859-1\"", false), "caf\u{e9}");
+        assert_eq!(decode(b"\xcf\xf0\xe8", "text/html;charset=windows-1251", true), "\u{41f}\u{440}\u{438}");
+        // Then a <meta> tag, for HTML only
+        let page = b"<html><head><meta charset=\"koi8-r\"></head>\xf0\xd2\xc9";
+        assert!(decode(page, "text/html", true).ends_with("\u{41f}\u{440}\u{438}"));
+        let page = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">\x93\xfa";
+        assert!(decode(page, "", true).ends_with("\u{65e5}"));
+        assert_eq!(decode(b"<meta charset=koi8-r>\xf0", "text/plain", false), "<meta charset=koi8-r>\u{f0}");
+        // A page can't claim UTF-16 in ASCII
+        assert_eq!(decode(b"<meta charset=utf-16>caf\xc3\xa9", "", true), "<meta charset=utf-16>caf\u{e9}");
+        // Otherwise UTF-8 if it is valid, windows-1252 if not
+        assert_eq!(decode("caf\u{e9}".as_bytes(), "", false), "caf\u{e9}");
+        assert_eq!(decode(b"caf\xe9 \x93q\x94", "", false), "caf\u{e9} \u{201c}q\u{201d}");
+    }
+
+    #[test]
+    fn disposition_file_names() {
+        let name = |header: &str| disposition_file_name(header);
+        assert_eq!(name("attachment; filename=\"report 2024.pdf\"").as_deref(), Some("report 2024.pdf"));
+        assert_eq!(name("attachment; filename=plain.txt").as_deref(), Some("plain.txt"));
+        assert_eq!(
+            name("attachment; filename=\"fallback.txt\"; filename*=UTF-8''r%C3%A9sum%C3%A9.txt").as_deref(),
+            Some("r\u{e9}sum\u{e9}.txt")
+        );
+        assert_eq!(name("attachment; FILENAME*=iso-8859-1'en'caf%E9.txt").as_deref(), Some("caf\u{e9}.txt"));
+        // Paths are cut down to the name
+        assert_eq!(name("attachment; filename=\"../../etc/passwd\"").as_deref(), Some("passwd"));
+        assert_eq!(name("attachment; filename=\"C:\\\\temp\\\\x.exe\"").as_deref(), Some("x.exe"));
+        assert_eq!(name("attachment; filename=\"..\""), None);
+        assert_eq!(name("attachment; filename=\"\""), None);
+        assert_eq!(name("attachment"), None);
+        assert_eq!(name("inline"), None);
+    }
+
+    #[test]
+    fn url_file_names() {
+        assert_eq!(file_name("https://example.com/files/My%20Report.pdf?x=1"), "My Report.pdf");
+        assert_eq!(file_name("https://example.com/a/b/"), "example.com");
+        assert_eq!(file_name("https://example.com"), "example.com");
+        assert_eq!(file_name("https://example.com/a%2Fb"), "a_b");
+        assert_eq!(file_name("not a url"), "download");
+    }
+}
@@ -0,0 +1,582 @@
+// Cookies: one jar shared by every request, following RFC 6265 for which
+// cookies go where, saved in a tab-separated file under the XDG data
+// directory and listed on the about:cookies page.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use url::Url;
+
+use crate::document::escape_html;
+use crate::keys::{Action, Keymap};
+use crate::paths;
+use crate::util::now;
+
+pub const PAGE_URL: &str = "about:cookies";
+
+// The cookies page filtered by `query`
+pub fn page_url(query: &str) -> String {
+    if query.trim().is_empty() {
+        return PAGE_URL.to_string();
+    }
+    let encoded: String = url::form_urlencoded::byte_serialize(query.trim().as_bytes()).collect();
+    format!("{}?q={}", PAGE_URL, encoded)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    // SameSite=None: sent along with cross-site requests too
+    Unrestricted,
+}
+
+impl SameSite {
+    fn name(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::Unrestricted => "None",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::Unrestricted),
+            _ => None,
+        }
+    }
+}
+
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    // Lowercase, without a leading dot
+    pub domain: String,
+    // Set without a Domain attribute: only sent back to that exact host
+    pub host_only: bool,
+    pub path: String,
+    // Seconds since the Unix epoch; None lasts until connex exits
+    pub expires: Option<i64>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+}
+
+// Which sites may set and receive cookies, from the [cookies] table of the
+// config file. The most specific entry matching a host wins.
+#[derive(Clone)]
+pub struct CookiePolicy {
+    pub accept: bool,
+    pub allow: Vec<String>,
+    pub block: Vec<String>,
+}
+
+impl Default for CookiePolicy {
+    fn default() -> Self {
+        CookiePolicy { accept: true, allow: Vec::new(), block: Vec::new() }
+    }
+}
+
+impl CookiePolicy {
+    pub fn allows(&self, host: &str) -> bool {
+        let longest = |domains: &[String]| domains.iter().filter(|d| domain_match(host, d)).map(String::len).max();
+        match (longest(&self.allow), longest(&self.block)) {
+            (Some(allow), Some(block)) => allow > block,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => self.accept,
+        }
+    }
+}
+
+// `host` is `domain` or one of its subdomains
+pub fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+// `path` is `cookie_path` or below it
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+// The directory of the request path, for cookies without a Path attribute
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => url.path()[..end].to_string(),
+    }
+}
+
+// Roughly the registrable domain, for telling same-site requests from
+// cross-site ones. Without the public suffix list this is the last two
+// labels, which is wrong for the likes of example.co.uk but errs on the
+// side of calling requests cross-site.
+fn site(host: &str) -> &str {
+    let mut dots = host.rmatch_indices('.');
+    dots.next();
+    match dots.next() {
+        Some((i, _)) => &host[i + 1..],
+        None => host,
+    }
+}
+
+// Cookie dates, e.g. "Wed, 21 Oct 2015 07:28:00 GMT", or with dashes in the
+// date as older servers send them
+fn parse_date(text: &str) -> Option<i64> {
+    // Only the date's dashes: a zone such as -0500 keeps its sign
+    let date_end = text.find(':').and_then(|colon| text[..colon].rfind(' ')).unwrap_or(0);
+    let text = format!("{}{}", text[..date_end].replace('-', " "), &text[date_end..]);
+    if let Ok(time) = DateTime::parse_from_rfc2822(&text) {
+        return Some(time.timestamp());
+    }
+    let text = text.trim_end_matches(" GMT");
+    ["%a, %d %b %Y %H:%M:%S", "%a %b %e %H:%M:%S %Y", "%A, %d %b %y %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .map(|time| time.and_utc().timestamp())
+}
+
+// Parse a Set-Cookie header received from `url`. None if the cookie is
+// malformed or not something that URL may set.
+fn parse_set_cookie(header: &str, url: &Url, now: i64) -> Option<Cookie> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        domain: host.clone(),
+        host_only: true,
+        path: default_path(url),
+        expires: None,
+        secure: false,
+        http_only: false,
+        same_site: SameSite::Lax,
+    };
+    let mut max_age = None;
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if domain.is_empty() {
+                    continue;
+                }
+                // A domain without dots would be a top-level domain
+                if !domain_match(&host, &domain) || (!domain.contains('.') && domain != host) {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "expires" => cookie.expires = parse_date(value).or(cookie.expires),
+            "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            "samesite" => cookie.same_site = SameSite::from_name(value).unwrap_or(SameSite::Lax),
+            _ => {}
+        }
+    }
+    // Max-Age wins over Expires
+    if let Some(seconds) = max_age {
+        cookie.expires = Some(now.saturating_add(seconds));
+    }
+
+    // Only secure pages may set secure cookies, and cross-site cookies must
+    // be secure
+    if cookie.secure && url.scheme() != "https" {
+        return None;
+    }
+    if cookie.same_site == SameSite::Unrestricted && !cookie.secure {
+        return None;
+    }
+    Some(cookie)
+}
+
+#[derive(Default)]
+pub struct CookieJar {
+    // Oldest first; locked because pages load on their own threads
+    cookies: Mutex<Vec<Cookie>>,
+    policy: CookiePolicy,
+    // Where cookies are saved; None keeps them in memory only
+    path: Option<PathBuf>,
+}
+
+impl CookieJar {
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("cookies.tsv"))
+    }
+
+    // Load cookies saved in `path`; a missing file is an empty jar
+    pub fn load(path: Option<PathBuf>, policy: CookiePolicy) -> io::Result<Self> {
+        let contents = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e),
+            },
+            None => String::new(),
+        };
+
+        let now = now() as i64;
+        let mut cookies = Vec::new();
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, host_only, path, secure, http_only, same_site, expires, name, value] = fields[..] else {
+                continue;
+            };
+            let Ok(expires) = expires.parse::<i64>() else { continue };
+            if expires <= now {
+                continue;
+            }
+            cookies.push(Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                domain: domain.to_string(),
+                host_only: host_only == "1",
+                path: path.to_string(),
+                expires: Some(expires),
+                secure: secure == "1",
+                http_only: http_only == "1",
+                same_site: SameSite::from_name(same_site).unwrap_or(SameSite::Lax),
+            });
+        }
+        Ok(CookieJar { cookies: Mutex::new(cookies), policy, path })
+    }
+
+    // Write out the cookies that outlive the session
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let now = now() as i64;
+        let mut contents = String::new();
+        for c in self.cookies.lock().unwrap().iter() {
+            let Some(expires) = c.expires.filter(|&e| e > now) else { continue };
+            let flag = |on: bool| if on { "1" } else { "0" };
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                c.domain,
+                flag(c.host_only),
+                c.path,
+                flag(c.secure),
+                flag(c.http_only),
+                c.same_site.name(),
+                expires,
+                c.name,
+                c.value
+            ));
+        }
+        // Write to a temporary file first so a crash can't lose every cookie.
+        // Cookies are as good as passwords, so only the user may read it; a
+        // leftover file is removed first since the mode only applies to new
+        // ones.
+        let temporary = path.with_extension("tsv.tmp");
+        let _ = fs::remove_file(&temporary);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&temporary)?.write_all(contents.as_bytes())?;
+        fs::rename(&temporary, path)
+    }
+
+    // Take in a Set-Cookie header from a response to `url`. A cookie that
+    // has already expired deletes the one it replaces.
+    pub fn store(&self, url: &Url, header: &str) {
+        let now = now() as i64;
+        let Some(cookie) = parse_set_cookie(header, url, now) else { return };
+        if !self.policy.allows(&cookie.domain) {
+            return;
+        }
+        // Values with tabs or newlines couldn't be saved
+        if [&cookie.name, &cookie.value, &cookie.path].iter().any(|f| f.contains(['\t', '\n', '\r'])) {
+            return;
+        }
+
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        if cookie.expires.is_none_or(|e| e > now) {
+            cookies.push(cookie);
+        }
+    }
+
+    // The Cookie header for a request to `url`. `initiator` is the page the
+    // request came from, if any; SameSite cookies are held back from
+    // cross-site requests, and Lax ones are only sent along with GETs.
+    pub fn header(&self, url: &Url, initiator: Option<&Url>, get: bool) -> Option<String> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        if !self.policy.allows(&host) {
+            return None;
+        }
+        let cross_site = initiator.and_then(Url::host_str).is_some_and(|from| site(&from.to_ascii_lowercase()) != site(&host));
+        let secure = url.scheme() == "https";
+        let now = now() as i64;
+
+        let cookies = self.cookies.lock().unwrap();
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|c| if c.host_only { host == c.domain } else { domain_match(&host, &c.domain) })
+            .filter(|c| path_match(url.path(), &c.path))
+            .filter(|c| secure || !c.secure)
+            .filter(|c| c.expires.is_none_or(|e| e > now))
+            .filter(|c| match c.same_site {
+                _ if !cross_site => true,
+                SameSite::Strict => false,
+                SameSite::Lax => get,
+                SameSite::Unrestricted => true,
+            })
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // Longer paths first, as RFC 6265 asks
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let pairs: Vec<String> = matching.iter().map(|c| format!("{}={}", c.name, c.value)).collect();
+        Some(pairs.join("; "))
+    }
+
+    // Delete the cookies of `domain`, or just the one called `name` there,
+    // returning how many went
+    pub fn remove(&self, domain: &str, name: Option<&str>, path: Option<&str>) -> usize {
+        let mut cookies = self.cookies.lock().unwrap();
+        let before = cookies.len();
+        cookies.retain(|c| !(c.domain == domain && name.is_none_or(|n| c.name == n) && path.is_none_or(|p| c.path == p)));
+        before - cookies.len()
+    }
+
+    // The about:cookies page: cookies grouped by domain, optionally filtered
+    // by domain or name, each with a link that deletes it. `notice` reports
+    // the outcome of a deletion.
+    pub fn to_html(&self, query: &str, notice: Option<&str>, keymap: &Keymap) -> String {
+        let mut html = String::from("<html><head><title>Cookies</title></head><body><h1>Cookies</h1>");
+        if let Some(notice) = notice {
+            html.push_str(&format!("<p><b>{}</b></p>", escape_html(notice)));
+        }
+
+        let policy = &self.policy;
+        html.push_str(&format!(
+            "<p>Cookies are {} unless listed below.",
+            if policy.accept { "accepted" } else { "blocked" }
+        ));
+        for (label, domains) in [("Always allowed", &policy.allow), ("Always blocked", &policy.block)] {
+            if !domains.is_empty() {
+                html.push_str(&format!(" {}: {}.", label, escape_html(&domains.join(", "))));
+            }
+        }
+        html.push_str(" Change this under [cookies] in the config file.</p>");
+
+        if !query.is_empty() {
+            html.push_str(&format!(
+                "<p>Showing cookies matching <b>{}</b>. <a href=\"{}\">Show all</a></p>",
+                escape_html(query),
+                PAGE_URL
+            ));
+        }
+
+        let now = now() as i64;
+        let cookies = self.cookies.lock().unwrap();
+        let query = query.to_lowercase();
+        let mut shown: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|c| c.expires.is_none_or(|e| e > now))
+            .filter(|c| query.split_whitespace().all(|word| format!("{} {}", c.domain, c.name.to_lowercase()).contains(word)))
+            .collect();
+        shown.sort_by(|a, b| a.domain.cmp(&b.domain).then_with(|| a.name.cmp(&b.name)));
+        if shown.is_empty() {
+            html.push_str("<p>No cookies.</p>");
+        }
+
+        let encode = |text: &str| -> String { url::form_urlencoded::byte_serialize(text.as_bytes()).collect() };
+        let mut domain = None;
+        for c in shown {
+            if domain != Some(&c.domain) {
+                if domain.is_some() {
+                    html.push_str("</ul>");
+                }
+                domain = Some(&c.domain);
+                html.push_str(&format!(
+                    "<h2>{}</h2><p><a href=\"{}?delete={}\">Delete all</a></p><ul>",
+                    escape_html(&c.domain),
+                    PAGE_URL,
+                    encode(&c.domain)
+                ));
+            }
+
+            let value: String = c.value.chars().take(60).collect();
+            let expires = match c.expires.and_then(|e| Local.timestamp_opt(e, 0).single()) {
+                Some(time) => format!("until {}", time.format("%-d %B %Y %H:%M")),
+                None => "until connex exits".to_string(),
+            };
+            let mut flags = vec![format!("SameSite={}", c.same_site.name())];
+            if c.secure {
+                flags.push("Secure".to_string());
+            }
+            if c.http_only {
+                flags.push("HttpOnly".to_string());
+            }
+            if c.host_only {
+                flags.push("this host only".to_string());
+            }
+            html.push_str(&format!(
+                "<li><b>{}</b>=<code>{}{}</code><br>path {}, {}, {} \
+                 <a href=\"{}?delete={}&amp;name={}&amp;path={}\">Delete</a></li>",
+                escape_html(&c.name),
+                escape_html(&value),
+                if value.len() < c.value.len() { "…" } else { "" },
+                escape_html(&c.path),
+                expires,
+                flags.join(", "),
+                PAGE_URL,
+                encode(&c.domain),
+                encode(&c.name),
+                encode(&c.path)
+            ));
+        }
+        if domain.is_some() {
+            html.push_str("</ul>");
+        }
+
+        html.push_str(&format!(
+            "<p><small>{}: filter by domain or name</small></p></body></html>",
+            escape_html(&keymap.keys_for(Action::Filter))
+        ));
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    #[test]
+    fn domain_and_path_matching() {
+        assert!(domain_match("example.com", "example.com"));
+        assert!(domain_match("www.example.com", "example.com"));
+        assert!(!domain_match("badexample.com", "example.com"));
+        assert!(!domain_match("example.com", "www.example.com"));
+
+        assert!(path_match("/docs", "/docs"));
+        assert!(path_match("/docs/page", "/docs"));
+        assert!(path_match("/docs/page", "/docs/"));
+        assert!(!path_match("/docsearch", "/docs"));
+        assert!(!path_match("/", "/docs"));
+    }
+
+    #[test]
+    fn domain_and_path_attributes() {
+        let page = url("https://www.example.com/docs/page");
+        let cookie = parse_set_cookie("a=1", &page, 0).unwrap();
+        assert_eq!((cookie.domain.as_str(), cookie.host_only, cookie.path.as_str()), ("www.example.com", true, "/docs"));
+
+        let cookie = parse_set_cookie("a=1; Domain=.Example.com; Path=/", &page, 0).unwrap();
+        assert_eq!((cookie.domain.as_str(), cookie.host_only, cookie.path.as_str()), ("example.com", false, "/"));
+
+        // Not for other sites, sibling hosts or a whole top-level domain
+        assert!(parse_set_cookie("a=1; Domain=other.com", &page, 0).is_none());
+        assert!(parse_set_cookie("a=1; Domain=api.example.com", &page, 0).is_none());
+        assert!(parse_set_cookie("a=1; Domain=com", &page, 0).is_none());
+    }
+
+    #[test]
+    fn host_only_and_path_scoping() {
+        let jar = CookieJar::default();
+        jar.store(&url("https://example.com/"), "host=1");
+        jar.store(&url("https://example.com/"), "wide=2; Domain=example.com");
+        jar.store(&url("https://example.com/"), "docs=3; Path=/docs");
+
+        assert_eq!(jar.header(&url("https://example.com/docs/a"), None, true).as_deref(), Some("docs=3; host=1; wide=2"));
+        assert_eq!(jar.header(&url("https://example.com/"), None, true).as_deref(), Some("host=1; wide=2"));
+        assert_eq!(jar.header(&url("https://www.example.com/"), None, true).as_deref(), Some("wide=2"));
+        assert_eq!(jar.header(&url("https://other.com/"), None, true), None);
+    }
+
+    #[test]
+    fn secure_rules() {
+        assert!(parse_set_cookie("a=1; Secure", &url("http://example.com/"), 0).is_none());
+        assert!(parse_set_cookie("a=1; Secure", &url("https://example.com/"), 0).is_some());
+        assert!(parse_set_cookie("a=1; SameSite=None", &url("https://example.com/"), 0).is_none());
+        assert!(parse_set_cookie("a=1; SameSite=None; Secure", &url("https://example.com/"), 0).is_some());
+
+        let jar = CookieJar::default();
+        jar.store(&url("https://example.com/"), "a=1; Secure");
+        assert_eq!(jar.header(&url("http://example.com/"), None, true), None);
+        assert_eq!(jar.header(&url("https://example.com/"), None, true).as_deref(), Some("a=1"));
+    }
+
+    #[test]
+    fn same_site_rules() {
+        let jar = CookieJar::default();
+        jar.store(&url("https://example.com/"), "strict=1; SameSite=Strict");
+        jar.store(&url("https://example.com/"), "lax=2; SameSite=Lax");
+        jar.store(&url("https://example.com/"), "none=3; SameSite=None; Secure");
+        let target = url("https://example.com/");
+        let same_site = url("https://www.example.com/");
+        let cross_site = url("https://other.com/");
+
+        assert_eq!(jar.header(&target, Some(&same_site), false).as_deref(), Some("strict=1; lax=2; none=3"));
+        assert_eq!(jar.header(&target, Some(&cross_site), true).as_deref(), Some("lax=2; none=3"));
+        assert_eq!(jar.header(&target, Some(&cross_site), false).as_deref(), Some("none=3"));
+    }
+
+    #[test]
+    fn max_age_wins_over_expires() {
+        let page = url("https://example.com/");
+        let expires = "Expires=Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(parse_set_cookie(&format!("a=1; {}", expires), &page, 0).unwrap().expires, Some(1445412480));
+        assert_eq!(parse_set_cookie(&format!("a=1; Max-Age=60; {}", expires), &page, 1000).unwrap().expires, Some(1060));
+        assert_eq!(parse_set_cookie(&format!("a=1; {}; Max-Age=60", expires), &page, 1000).unwrap().expires, Some(1060));
+        assert_eq!(parse_set_cookie("a=1", &page, 0).unwrap().expires, None);
+    }
+
+    #[test]
+    fn expires_dates() {
+        assert_eq!(parse_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(1445412480));
+        assert_eq!(parse_date("Wed, 21-Oct-2015 07:28:00 GMT"), Some(1445412480));
+        assert_eq!(parse_date("Wednesday, 21-Oct-15 07:28:00 GMT"), Some(1445412480));
+        assert_eq!(parse_date("Wed, 21 Oct 2015 02:28:00 -0500"), Some(1445412480));
+        assert_eq!(parse_date("Wed, 21-Oct-2015 09:28:00 +0200"), Some(1445412480));
+        assert_eq!(parse_date("soon"), None);
+    }
+
+    #[test]
+    fn policy_most_specific_entry_wins() {
+        let policy = CookiePolicy {
+            accept: false,
+            allow: vec!["example.com".to_string()],
+            block: vec!["ads.example.com".to_string(), "tracker.net".to_string()],
+        };
+        assert!(policy.allows("example.com"));
+        assert!(policy.allows("www.example.com"));
+        assert!(!policy.allows("ads.example.com"));
+        assert!(!policy.allows("x.ads.example.com"));
+        assert!(!policy.allows("tracker.net"));
+        assert!(!policy.allows("other.org"));
+
+        let accept_all = CookiePolicy { accept: true, ..CookiePolicy::default() };
+        assert!(accept_all.allows("other.org"));
+        // An entry in both lists is blocked
+        let both = CookiePolicy { accept: true, allow: vec!["a.com".to_string()], block: vec!["a.com".to_string()] };
+        assert!(!both.allows("a.com"));
+    }
+}
@@ -0,0 +1,804 @@
+// HTML parsing into a width-independent document model.
+//
+// The parser flattens the DOM into blocks of inline runs; wrapping those
+// runs into screen lines is the job of the layout module, so a page only
+// needs to be parsed once no matter how often the terminal is resized.
+
+use scraper::{ElementRef, Html, Selector};
+use tui::style::{Color, Modifier, Style};
+use url::Url;
+
+use crate::form::{Enctype, Field, FieldKind, Form, Method};
+
+pub struct Link {
+    pub url: String,
+    #[allow(dead_code)]
+    pub display_text: String,
+    // The form field this link stands for; following it edits the field
+    pub field: Option<usize>,
+}
+
+// A piece of inline text sharing one style, optionally part of a link
+#[derive(Clone)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<usize>,
+}
+
+pub enum Block {
+    // Inline runs flowed and wrapped together, indented by `indent` columns.
+    // A list item's `marker` hangs in the indentation of its first line.
+    // Headings are marked for jumping between them.
+    Paragraph { runs: Vec<Run>, indent: usize, marker: Option<String>, heading: bool },
+    // Lines kept verbatim and never wrapped, shaded with `style`
+    Preformatted { lines: Vec<Vec<Run>>, style: Style, indent: usize },
+    Table(Table),
+    // Vertical space between paragraphs
+    Blank,
+}
+
+pub struct TableCell {
+    // Cell contents, laid out like a small document of their own
+    pub blocks: Vec<Block>,
+    pub header: bool,
+    pub colspan: usize,
+    // 0 means "to the end of the table", as in HTML
+    pub rowspan: usize,
+}
+
+pub struct Table {
+    pub rows: Vec<Vec<TableCell>>,
+    pub indent: usize,
+}
+
+pub struct Document {
+    // Contents of <title>, whitespace collapsed
+    pub title: Option<String>,
+    pub links: Vec<Link>,
+    pub blocks: Vec<Block>,
+    pub forms: Vec<Form>,
+    pub fields: Vec<Field>,
+}
+
+impl Document {
+    // A document holding a single message, used for error pages
+    pub fn from_text(text: &str) -> Self {
+        let run = Run { text: text.to_string(), style: Style::default(), link: None };
+        let paragraph = Block::Paragraph { runs: vec![run], indent: 0, marker: None, heading: false };
+        Document { title: None, links: Vec::new(), blocks: vec![paragraph], forms: Vec::new(), fields: Vec::new() }
+    }
+
+    // A text file, shown line for line as it is
+    pub fn from_plain_text(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| match line {
+                "" => Vec::new(),
+                _ => vec![Run { text: line.to_string(), style: Style::default(), link: None }],
+            })
+            .collect();
+        let block = Block::Preformatted { lines, style: Style::default(), indent: 0 };
+        Document { title: None, links: Vec::new(), blocks: vec![block], forms: Vec::new(), fields: Vec::new() }
+    }
+
+    // Redraw a form field in the page text after it changes
+    pub fn refresh_field(&mut self, field: usize) {
+        let field = &self.fields[field];
+        if let Some(link) = field.link {
+            set_link_text(&mut self.blocks, link, &field.widget());
+        }
+    }
+}
+
+// Replace the text of the run belonging to `link`, wherever it is
+fn set_link_text(blocks: &mut [Block], link: usize, text: &str) {
+    for block in blocks {
+        let runs: Box<dyn Iterator<Item = &mut Run>> = match block {
+            Block::Paragraph { runs, .. } => Box::new(runs.iter_mut()),
+            Block::Preformatted { lines, .. } => Box::new(lines.iter_mut().flatten()),
+            Block::Table(table) => {
+                for cell in table.rows.iter_mut().flatten() {
+                    set_link_text(&mut cell.blocks, link, text);
+                }
+                continue;
+            }
+            Block::Blank => continue,
+        };
+        for run in runs.filter(|run| run.link == Some(link)) {
+            run.text = text.to_string();
+        }
+    }
+}
+
+// Escape text for inclusion in generated HTML, such as the about: pages
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Elements that start a new line
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "center", "dd", "details", "div", "dl",
+    "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tr",
+    "ul",
+];
+
+// Block elements that are also separated from their surroundings by a blank line
+const SPACED_TAGS: &[&str] = &[
+    "blockquote", "dl", "figure", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "ol", "p", "pre",
+    "table", "ul",
+];
+
+// Elements whose whitespace is preserved
+const PREFORMATTED_TAGS: &[&str] = &["listing", "plaintext", "pre", "xmp"];
+
+// Elements whose contents are never rendered
+const HIDDEN_TAGS: &[&str] = &["head", "noscript", "script", "style", "template", "title"];
+
+// Style applied to the contents of an element, layered over its parents'
+fn tag_style(tag: &str) -> Option<Style> {
+    let style = Style::default();
+    Some(match tag {
+        "h1" => style.fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        "h2" => style.fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        "h3" => style.fg(Color::Green).add_modifier(Modifier::BOLD),
+        "h4" | "h5" | "h6" => style.add_modifier(Modifier::BOLD),
+        "strong" | "b" => style.add_modifier(Modifier::BOLD),
+        "em" | "i" | "cite" | "dfn" | "var" => style.add_modifier(Modifier::ITALIC),
+        "u" | "ins" => style.add_modifier(Modifier::UNDERLINED),
+        "del" | "s" | "strike" => style.add_modifier(Modifier::CROSSED_OUT),
+        "code" | "kbd" | "samp" | "tt" => style.fg(Color::Yellow),
+        "mark" => style.add_modifier(Modifier::REVERSED),
+        "pre" | "listing" | "plaintext" | "xmp" => style.bg(Color::Indexed(236)),
+        "small" | "sub" | "sup" => style.add_modifier(Modifier::DIM),
+        "dt" | "th" | "caption" => style.add_modifier(Modifier::BOLD),
+        _ => return None,
+    })
+}
+
+// Columns added for each level of list nesting
+const LIST_INDENT: usize = 4;
+
+// Numbering of an open <ol>, or bullet style of an open <ul>
+struct List {
+    ordered: bool,
+    // `type` attribute: 1, a, A, i or I for <ol>; disc, circle or square for <ul>
+    kind: String,
+    next: i64,
+    step: i64,
+}
+
+impl List {
+    fn from_element(element: &ElementRef, depth: usize) -> Self {
+        let attr = |name| element.value().attr(name);
+        if element.value().name() == "ul" {
+            let default = ["disc", "circle", "square"][depth % 3];
+            let kind = attr("type").unwrap_or(default).to_ascii_lowercase();
+            return List { ordered: false, kind, next: 0, step: 0 };
+        }
+
+        let reversed = attr("reversed").is_some();
+        let items = element.children().filter_map(ElementRef::wrap).filter(|e| e.value().name() == "li").count() as i64;
+        let next = attr("start")
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(if reversed { items } else { 1 });
+        let kind = attr("type").unwrap_or("1").to_string();
+        List { ordered: true, kind, next, step: if reversed { -1 } else { 1 } }
+    }
+
+    // Marker for the next <li>; `value` is the item's own value attribute
+    fn next_marker(&mut self, value: Option<i64>) -> String {
+        if !self.ordered {
+            return match self.kind.as_str() {
+                "circle" => "◦",
+                "square" => "▪",
+                _ => "•",
+            }
+            .to_string();
+        }
+
+        let n = value.unwrap_or(self.next);
+        self.next = n + self.step;
+        let label = match self.kind.as_str() {
+            "a" => alphabetic(n),
+            "A" => alphabetic(n).map(|s| s.to_uppercase()),
+            "i" => roman(n),
+            "I" => roman(n).map(|s| s.to_uppercase()),
+            _ => None,
+        };
+        format!("{}.", label.unwrap_or_else(|| n.to_string()))
+    }
+}
+
+// 1 -> a, 26 -> z, 27 -> aa
+fn alphabetic(mut n: i64) -> Option<String> {
+    if n < 1 {
+        return None;
+    }
+    let mut label = Vec::new();
+    while n > 0 {
+        n -= 1;
+        label.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    label.reverse();
+    String::from_utf8(label).ok()
+}
+
+fn roman(mut n: i64) -> Option<String> {
+    if !(1..4000).contains(&n) {
+        return None;
+    }
+    const NUMERALS: &[(i64, &str)] = &[
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut label = String::new();
+    for &(value, numeral) in NUMERALS {
+        while n >= value {
+            label.push_str(numeral);
+            n -= value;
+        }
+    }
+    Some(label)
+}
+
+// Parser state threaded through the recursive walk
+struct ParseState<'a> {
+    base_url: &'a Url,
+    links: Vec<Link>,
+    blocks: Vec<Block>,
+    // Runs of the paragraph currently being built
+    runs: Vec<Run>,
+    // Index of the link we are inside of, if any
+    link: Option<usize>,
+    // Styles of the enclosing elements, innermost last
+    styles: Vec<Style>,
+    // Depth of nested preformatted elements; whitespace is kept while non-zero
+    preformatted: usize,
+    // Current indentation and the lists we are inside of, innermost last
+    indent: usize,
+    lists: Vec<List>,
+    // Marker of a list item whose first paragraph hasn't been emitted yet
+    marker: Option<String>,
+    // Depth of nested h1-h6 elements
+    headings: usize,
+    forms: Vec<Form>,
+    fields: Vec<Field>,
+    // Index of the form we are inside of, if any
+    form: Option<usize>,
+}
+
+impl ParseState<'_> {
+    fn style(&self) -> Style {
+        self.styles.iter().fold(Style::default(), |acc, style| acc.patch(*style))
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let style = self.style();
+        if self.preformatted > 0 {
+            let text = text.replace('\r', "");
+            match self.runs.last_mut() {
+                Some(last) if last.style == style && last.link == self.link => last.text.push_str(&text),
+                _ => self.runs.push(Run { text, style, link: self.link }),
+            }
+            return;
+        }
+
+        // Collapse whitespace the way HTML does
+        let mut collapsed = String::with_capacity(text.len());
+        let mut last_space = self.runs.last().is_none_or(|r| r.text.ends_with(' '));
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !last_space {
+                    collapsed.push(' ');
+                }
+                last_space = true;
+            } else {
+                collapsed.push(c);
+                last_space = false;
+            }
+        }
+        if collapsed.is_empty() {
+            return;
+        }
+
+        match self.runs.last_mut() {
+            Some(last) if last.style == style && last.link == self.link => last.text.push_str(&collapsed),
+            _ => self.runs.push(Run { text: collapsed, style, link: self.link }),
+        }
+    }
+
+    // Add a form field, drawn as a link unless it's hidden
+    fn push_field(&mut self, mut field: Field) {
+        field.initial = (field.value.clone(), field.checked, field.selected);
+        if field.kind != FieldKind::Hidden {
+            let link = self.links.len();
+            let url = field.form.map_or_else(|| self.base_url.to_string(), |form| self.forms[form].action.clone());
+            let display_text = if field.label.is_empty() { field.name.clone() } else { field.label.clone() };
+            self.links.push(Link { url, display_text, field: Some(self.fields.len()) });
+            field.link = Some(link);
+            self.runs.push(Run { text: field.widget(), style: self.style(), link: Some(link) });
+        }
+        self.fields.push(field);
+    }
+
+    fn end_paragraph(&mut self) {
+        // Block elements inside <pre> don't break its lines
+        if self.preformatted > 0 {
+            return;
+        }
+        while let Some(last) = self.runs.last_mut() {
+            let trimmed = last.text.trim_end().len();
+            last.text.truncate(trimmed);
+            if !last.text.is_empty() {
+                break;
+            }
+            self.runs.pop();
+        }
+        if !self.runs.is_empty() {
+            let runs = std::mem::take(&mut self.runs);
+            self.blocks.push(Block::Paragraph {
+                runs,
+                indent: self.indent,
+                marker: self.marker.take(),
+                heading: self.headings > 0,
+            });
+        }
+    }
+
+    // Split the collected runs into verbatim lines
+    fn end_preformatted(&mut self, style: Style) {
+        let mut lines: Vec<Vec<Run>> = vec![Vec::new()];
+        for run in std::mem::take(&mut self.runs) {
+            for (i, part) in run.text.split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(Vec::new());
+                }
+                if !part.is_empty() {
+                    lines.last_mut().unwrap().push(Run { text: part.to_string(), ..run.clone() });
+                }
+            }
+        }
+
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        if !lines.is_empty() {
+            self.flush_marker();
+            self.blocks.push(Block::Preformatted { lines, style, indent: self.indent });
+        }
+    }
+
+    // Give a pending list marker a line of its own, for items that start
+    // with something other than text (a nested list, a code block)
+    fn flush_marker(&mut self) {
+        if let Some(marker) = self.marker.take() {
+            self.blocks.push(Block::Paragraph {
+                runs: Vec::new(),
+                indent: self.indent,
+                marker: Some(marker),
+                heading: false,
+            });
+        }
+    }
+
+    fn blank_line(&mut self) {
+        self.end_paragraph();
+        if !matches!(self.blocks.last(), None | Some(Block::Blank)) {
+            self.blocks.push(Block::Blank);
+        }
+    }
+}
+
+// Parse the contents of a table cell (or caption) into blocks of their own,
+// sharing the page's link list
+fn parse_cell(cell: &ElementRef, state: &mut ParseState) -> Vec<Block> {
+    let saved_blocks = std::mem::take(&mut state.blocks);
+    let saved_runs = std::mem::take(&mut state.runs);
+    let saved_indent = std::mem::replace(&mut state.indent, 0);
+    let saved_lists = std::mem::take(&mut state.lists);
+    let saved_marker = state.marker.take();
+
+    let style = tag_style(cell.value().name());
+    if let Some(style) = style {
+        state.styles.push(style);
+    }
+    parse_children(cell, state);
+    state.end_paragraph();
+    state.flush_marker();
+    if style.is_some() {
+        state.styles.pop();
+    }
+
+    let mut blocks = std::mem::replace(&mut state.blocks, saved_blocks);
+    state.runs = saved_runs;
+    state.indent = saved_indent;
+    state.lists = saved_lists;
+    state.marker = saved_marker;
+
+    while let Some(Block::Blank) = blocks.last() {
+        blocks.pop();
+    }
+    if let Some(Block::Blank) = blocks.first() {
+        blocks.remove(0);
+    }
+    blocks
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn parse_form(element: &ElementRef, state: &ParseState) -> Form {
+    let attr = |name| element.value().attr(name).map(str::trim).filter(|v| !v.is_empty());
+    let action = attr("action")
+        .and_then(|action| state.base_url.join(action).ok())
+        .unwrap_or_else(|| state.base_url.clone());
+    let method = match attr("method") {
+        Some(method) if method.eq_ignore_ascii_case("post") => Method::Post,
+        _ => Method::Get,
+    };
+    let enctype = match attr("enctype") {
+        Some(enctype) if enctype.eq_ignore_ascii_case("multipart/form-data") => Enctype::Multipart,
+        _ => Enctype::UrlEncoded,
+    };
+    Form { action: action.to_string(), method, enctype }
+}
+
+// <input>, <select>, <textarea> or <button>
+fn parse_field(element: &ElementRef, state: &ParseState) -> Field {
+    let tag = element.value().name();
+    let attr = |name| element.value().attr(name);
+    let input_type = attr("type").unwrap_or("").to_ascii_lowercase();
+    let kind = match (tag, input_type.as_str()) {
+        ("select", _) => FieldKind::Select,
+        ("textarea", _) => FieldKind::TextArea,
+        ("button", "reset") | ("input", "reset") => FieldKind::Reset,
+        ("button", "button") | ("input", "button") => FieldKind::Button,
+        ("button", _) | ("input", "submit" | "image") => FieldKind::Submit,
+        (_, "hidden") => FieldKind::Hidden,
+        (_, "password") => FieldKind::Password,
+        (_, "checkbox") => FieldKind::Checkbox,
+        (_, "radio") => FieldKind::Radio,
+        (_, "file") => FieldKind::File,
+        // text, search, email, number and the rest are all edited as text
+        _ => FieldKind::Text,
+    };
+
+    let name = attr("name").unwrap_or("").to_string();
+    let mut value = attr("value").unwrap_or("").to_string();
+    let mut options = Vec::new();
+    let mut selected = 0;
+    let mut label = match kind {
+        FieldKind::Submit if tag == "button" => collapse_whitespace(&element.text().collect::<String>()),
+        FieldKind::Submit | FieldKind::Reset | FieldKind::Button => {
+            attr("value").or(attr("alt")).unwrap_or("").to_string()
+        }
+        _ => ["placeholder", "aria-label", "title"].iter().find_map(|&a| attr(a)).unwrap_or("").to_string(),
+    };
+
+    match kind {
+        FieldKind::Select => {
+            let option_selector = Selector::parse("option").unwrap();
+            for (i, option) in element.select(&option_selector).enumerate() {
+                let text = collapse_whitespace(&option.text().collect::<String>());
+                let value = option.value().attr("value").map_or_else(|| text.clone(), str::to_string);
+                if option.value().attr("selected").is_some() {
+                    selected = i;
+                }
+                options.push((text, value));
+            }
+        }
+        FieldKind::TextArea => {
+            let text: String = element.text().collect();
+            // A newline straight after the start tag isn't part of the value
+            value = text.strip_prefix('\n').unwrap_or(&text).to_string();
+        }
+        FieldKind::Checkbox | FieldKind::Radio if value.is_empty() => value = "on".to_string(),
+        FieldKind::Button if tag == "button" => label = collapse_whitespace(&element.text().collect::<String>()),
+        FieldKind::File => value.clear(),
+        _ => {}
+    }
+    if label.is_empty() {
+        label = match kind {
+            FieldKind::Submit => "Submit".to_string(),
+            FieldKind::Reset => "Reset".to_string(),
+            _ => name.clone(),
+        };
+    }
+
+    let size = match kind {
+        FieldKind::TextArea => attr("cols").and_then(|c| c.trim().parse().ok()).unwrap_or(40),
+        _ => attr("size").and_then(|s| s.trim().parse().ok()).unwrap_or(20),
+    };
+
+    Field {
+        kind,
+        form: state.form,
+        name,
+        value,
+        checked: attr("checked").is_some(),
+        options,
+        selected,
+        label,
+        size: size.clamp(1, 60),
+        disabled: attr("disabled").is_some(),
+        link: None,
+        initial: (String::new(), false, 0),
+    }
+}
+
+fn span_attr(cell: &ElementRef, name: &str) -> usize {
+    cell.value().attr(name).and_then(|v| v.trim().parse().ok()).unwrap_or(1)
+}
+
+// Collect rows from a table, looking through thead/tbody/tfoot
+fn parse_table_rows(element: &ElementRef, state: &mut ParseState, rows: &mut Vec<Vec<TableCell>>, captions: &mut Vec<Block>) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        match child.value().name() {
+            "thead" | "tbody" | "tfoot" => parse_table_rows(&child, state, rows, captions),
+            "caption" => captions.extend(parse_cell(&child, state)),
+            "tr" => {
+                let mut row = Vec::new();
+                for cell in child.children().filter_map(ElementRef::wrap) {
+                    let tag = cell.value().name();
+                    if tag != "td" && tag != "th" {
+                        continue;
+                    }
+                    row.push(TableCell {
+                        blocks: parse_cell(&cell, state),
+                        header: tag == "th",
+                        colspan: span_attr(&cell, "colspan").clamp(1, 1000),
+                        rowspan: span_attr(&cell, "rowspan").min(65534),
+                    });
+                }
+                rows.push(row);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_table(element: &ElementRef, state: &mut ParseState) {
+    let mut rows = Vec::new();
+    let mut captions = Vec::new();
+    parse_table_rows(element, state, &mut rows, &mut captions);
+
+    state.flush_marker();
+    for block in captions {
+        state.blocks.push(block);
+    }
+
+    // Tables used only for page layout are rendered as their plain contents
+    let presentational = element.value().attr("role") == Some("presentation");
+    let single_cell = rows.iter().map(Vec::len).sum::<usize>() <= 1;
+    if presentational || single_cell {
+        for cell in rows.into_iter().flatten() {
+            for block in cell.blocks {
+                state.blocks.push(block);
+            }
+            state.blank_line();
+        }
+        return;
+    }
+
+    rows.retain(|row| !row.is_empty());
+    if !rows.is_empty() {
+        state.blocks.push(Block::Table(Table { rows, indent: state.indent }));
+    }
+}
+
+// A <code> with lines of its own and nothing else around it in its parent,
+// as code samples are often written without <pre>
+fn is_code_block(element: &ElementRef) -> bool {
+    let multiline = element.text().collect::<String>().trim().contains('\n');
+    let standalone = element.parent().is_some_and(|parent| {
+        parent
+            .children()
+            .all(|child| child == **element || child.value().as_text().is_some_and(|t| t.trim().is_empty()))
+    });
+    multiline && standalone
+}
+
+// Recursive parse function using ElementRef
+fn parse_element(element: &ElementRef, state: &mut ParseState) {
+    let tag = element.value().name();
+
+    if HIDDEN_TAGS.contains(&tag) {
+        return;
+    }
+
+    if tag == "a" {
+        if let Some(href) = element.value().attr("href") {
+            let url = if href.starts_with("http") {
+                href.to_string()
+            } else {
+                state.base_url.join(href).map(|u| u.to_string()).unwrap_or_else(|_| href.to_string())
+            };
+            let display_text = element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+            if !display_text.is_empty() {
+                state.link = Some(state.links.len());
+                state.links.push(Link { url, display_text, field: None });
+                parse_children(element, state);
+                state.link = None;
+            }
+            return;
+        }
+    }
+
+    if matches!(tag, "input" | "select" | "textarea" | "button") {
+        let field = parse_field(element, state);
+        state.push_field(field);
+        return;
+    }
+
+    if tag == "table" && state.preformatted == 0 {
+        state.blank_line();
+        parse_table(element, state);
+        state.blank_line();
+        return;
+    }
+
+    let is_list = tag == "ul" || tag == "ol";
+    // Nested lists hug their parent item instead of being set apart
+    let nested_list = is_list && !state.lists.is_empty();
+    // Kept verbatim and set apart like <pre>
+    let code_block = tag == "code" && state.preformatted == 0 && is_code_block(element);
+    let spaced = (SPACED_TAGS.contains(&tag) && !nested_list) || code_block;
+    if spaced {
+        state.blank_line();
+    } else if BLOCK_TAGS.contains(&tag) {
+        state.end_paragraph();
+    }
+
+    let heading = matches!(tag, "h1" | "h2" | "h3" | "h4" | "h5" | "h6");
+    if heading {
+        state.headings += 1;
+    }
+
+    let style = tag_style(tag);
+    if let Some(style) = style {
+        state.styles.push(style);
+    }
+    if code_block {
+        state.styles.push(tag_style("pre").unwrap());
+    }
+
+    let enclosing_form = match tag {
+        "form" => {
+            let form = parse_form(element, state);
+            state.forms.push(form);
+            Some(state.form.replace(state.forms.len() - 1))
+        }
+        _ => None,
+    };
+
+    let preformatted = PREFORMATTED_TAGS.contains(&tag) || code_block;
+    if preformatted {
+        state.preformatted += 1;
+    }
+
+    let indented = is_list || tag == "dd";
+    if is_list {
+        state.flush_marker();
+        state.lists.push(List::from_element(element, state.lists.len()));
+    }
+    if indented {
+        state.indent += LIST_INDENT;
+    }
+    if tag == "li" {
+        let value = element.value().attr("value").and_then(|v| v.trim().parse().ok());
+        state.marker = match state.lists.last_mut() {
+            Some(list) => Some(list.next_marker(value)),
+            None => Some("•".to_string()),
+        };
+    }
+
+    parse_children(element, state);
+
+    if tag == "li" {
+        // An empty item still gets its marker
+        state.end_paragraph();
+        state.flush_marker();
+    }
+
+    if preformatted {
+        state.preformatted -= 1;
+        // The parser drops the line break after <pre>, but not after <code>
+        if let Some(first) = state.runs.first_mut().filter(|_| code_block) {
+            if first.text.starts_with('\n') {
+                first.text.remove(0);
+            }
+        }
+        if state.preformatted == 0 {
+            state.end_preformatted(state.style());
+        }
+    }
+    if code_block {
+        state.styles.pop();
+    }
+    if style.is_some() {
+        state.styles.pop();
+    }
+
+    // Now close the block *only* if the current element is a block element
+    if spaced {
+        state.blank_line();
+    } else if BLOCK_TAGS.contains(&tag) {
+        state.end_paragraph();
+    }
+    if heading {
+        state.headings -= 1;
+    }
+
+    if indented {
+        state.indent -= LIST_INDENT;
+    }
+    if is_list {
+        state.lists.pop();
+    }
+    if let Some(form) = enclosing_form {
+        state.form = form;
+    }
+}
+
+fn parse_children(element: &ElementRef, state: &mut ParseState) {
+    for child in element.children() {
+        if let Some(child_element) = ElementRef::wrap(child) {
+            parse_element(&child_element, state);
+        } else if let Some(text) = child.value().as_text() {
+            state.push_text(text);
+        }
+    }
+}
+
+pub fn parse_html(html: &str, base_url: &Url) -> Document {
+    let document = Html::parse_document(html);
+    let body_selector = Selector::parse("body").unwrap();
+    let title_selector = Selector::parse("title").unwrap();
+
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|t| t.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|t| !t.is_empty());
+
+    let mut state = ParseState {
+        base_url,
+        links: Vec::new(),
+        blocks: Vec::new(),
+        runs: Vec::new(),
+        link: None,
+        styles: Vec::new(),
+        preformatted: 0,
+        indent: 0,
+        lists: Vec::new(),
+        marker: None,
+        headings: 0,
+        forms: Vec::new(),
+        fields: Vec::new(),
+        form: None,
+    };
+
+    if let Some(body) = document.select(&body_selector).next() {
+        parse_element(&body, &mut state);
+    }
+    state.end_paragraph();
+    if let Some(Block::Blank) = state.blocks.last() {
+        state.blocks.pop();
+    }
+
+    Document { title, links: state.links, blocks: state.blocks, forms: state.forms, fields: state.fields }
+}
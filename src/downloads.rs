@@ -0,0 +1,404 @@
+// Downloads: files saved to disk on their own threads so browsing carries
+// on, written to a .part file that a later attempt resumes with a Range
+// request if the file hasn't changed, and listed on the about:downloads page.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use reqwest::blocking;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::document::escape_html;
+use crate::http::{self, FetchError, HttpClient, Request};
+use crate::loader::format_bytes;
+
+pub const PAGE_URL: &str = "about:downloads";
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum State {
+    Active,
+    Finished,
+    Failed(String),
+    Cancelled,
+}
+
+struct Download {
+    // Stays the same as others are cleared from the list
+    id: usize,
+    url: String,
+    path: PathBuf,
+    received: u64,
+    total: Option<u64>,
+    state: State,
+    // For the transfer rate: when this attempt started and how much was
+    // already on disk then
+    started: Instant,
+    resumed_from: u64,
+    cancel: Arc<AtomicBool>,
+    // Whether its end has been reported on the status line
+    announced: bool,
+}
+
+// Shared with the threads doing the downloading; cloning shares the list
+#[derive(Clone, Default)]
+pub struct Downloads {
+    list: Arc<Mutex<Vec<Download>>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+fn find(list: &mut [Download], id: usize) -> Result<&mut Download, String> {
+    list.iter_mut().find(|d| d.id == id).ok_or_else(|| "No such download".to_string())
+}
+
+// Where a download is written until it completes
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+// Next to the .part file: the URL it is from and the validator (ETag or
+// Last-Modified) of the response it holds, for checking before resuming
+fn info_path(path: &Path) -> PathBuf {
+    let mut name = part_path(path).into_os_string();
+    name.push(".info");
+    PathBuf::from(name)
+}
+
+fn remove_part(path: &Path) {
+    let _ = fs::remove_file(part_path(path));
+    let _ = fs::remove_file(info_path(path));
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+// e.g. "[======    ] 60%"
+fn progress_bar(received: u64, total: Option<u64>, width: usize) -> String {
+    match total.filter(|&total| total > 0) {
+        Some(total) => {
+            let fraction = (received as f64 / total as f64).min(1.0);
+            let filled = (fraction * width as f64).round() as usize;
+            format!("[{}{}] {:.0}%", "=".repeat(filled), " ".repeat(width - filled), fraction * 100.0)
+        }
+        None => format_bytes(received),
+    }
+}
+
+impl Downloads {
+    // Start saving `url` to `path`. An earlier attempt's .part file is
+    // resumed rather than started over.
+    pub fn start(&self, url: &str, path: PathBuf, client: &HttpClient) -> Result<(), String> {
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()));
+        }
+        let mut list = self.list.lock().unwrap();
+        if list.iter().any(|d| d.path == path && d.state == State::Active) {
+            return Err(format!("{} is already being downloaded", path.display()));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        list.push(Download {
+            id,
+            url: url.to_string(),
+            path,
+            received: 0,
+            total: None,
+            state: State::Active,
+            started: Instant::now(),
+            resumed_from: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+            announced: false,
+        });
+        drop(list);
+        self.spawn(id, client);
+        Ok(())
+    }
+
+    // Try a failed or cancelled download again, from where it stopped
+    pub fn resume(&self, id: usize, client: &HttpClient) -> Result<String, String> {
+        let mut list = self.list.lock().unwrap();
+        let download = find(&mut list, id)?;
+        match download.state {
+            State::Active => return Err(format!("{} is already downloading", file_name(&download.path))),
+            State::Finished => return Err(format!("{} has finished", file_name(&download.path))),
+            State::Failed(_) | State::Cancelled => {}
+        }
+        download.state = State::Active;
+        download.cancel = Arc::new(AtomicBool::new(false));
+        download.announced = false;
+        let name = file_name(&download.path);
+        drop(list);
+        self.spawn(id, client);
+        Ok(format!("Resuming {}", name))
+    }
+
+    // Stop a download and throw away what it has received
+    pub fn cancel(&self, id: usize) -> Result<String, String> {
+        let mut list = self.list.lock().unwrap();
+        let download = find(&mut list, id)?;
+        match download.state {
+            State::Active => download.cancel.store(true, Ordering::Relaxed),
+            // Nothing running will clean up after it
+            State::Failed(_) => {
+                remove_part(&download.path);
+                download.state = State::Cancelled;
+                download.announced = true;
+            }
+            State::Finished => return Err(format!("{} has finished", file_name(&download.path))),
+            State::Cancelled => return Err(format!("{} was cancelled", file_name(&download.path))),
+        }
+        Ok(format!("Cancelled {}", file_name(&download.path)))
+    }
+
+    // Forget finished and cancelled downloads; the files stay on disk
+    pub fn clear(&self) -> String {
+        let mut list = self.list.lock().unwrap();
+        let before = list.len();
+        list.retain(|d| matches!(d.state, State::Active | State::Failed(_)));
+        let cleared = before - list.len();
+        format!("Cleared {} download{}", cleared, if cleared == 1 { "" } else { "s" })
+    }
+
+    fn spawn(&self, id: usize, client: &HttpClient) {
+        let list = Arc::clone(&self.list);
+        let client = client.clone();
+        let (url, path, cancel) = {
+            let mut list = list.lock().unwrap();
+            let Ok(download) = find(&mut list, id) else { return };
+            (download.url.clone(), download.path.clone(), Arc::clone(&download.cancel))
+        };
+        thread::spawn(move || {
+            let result = run(&list, id, &client, &url, &path, &cancel);
+            let mut list = list.lock().unwrap();
+            let Ok(download) = find(&mut list, id) else { return };
+            download.state = match result {
+                Ok(()) => State::Finished,
+                Err(_) if cancel.load(Ordering::Relaxed) => {
+                    remove_part(&path);
+                    State::Cancelled
+                }
+                // The .part file is kept for resuming
+                Err(e) => State::Failed(e.to_string()),
+            };
+        });
+    }
+
+    pub fn any_active(&self) -> bool {
+        self.list.lock().unwrap().iter().any(|d| d.state == State::Active)
+    }
+
+    // Progress of the running downloads for the status line, e.g.
+    // "Downloading file.pdf [====      ] 40%"
+    pub fn status(&self) -> Option<String> {
+        let list = self.list.lock().unwrap();
+        let active: Vec<&Download> = list.iter().filter(|d| d.state == State::Active).collect();
+        match active[..] {
+            [] => None,
+            [download] => Some(format!(
+                "Downloading {} {}",
+                file_name(&download.path),
+                progress_bar(download.received, download.total, 20)
+            )),
+            _ => {
+                let received = active.iter().map(|d| d.received).sum();
+                let total = active.iter().map(|d| d.total).sum::<Option<u64>>();
+                Some(format!("Downloading {} files {}", active.len(), progress_bar(received, total, 20)))
+            }
+        }
+    }
+
+    // Downloads that have ended since the last call, to report once
+    pub fn take_ended(&self) -> Vec<String> {
+        let mut list = self.list.lock().unwrap();
+        let mut ended = Vec::new();
+        for download in list.iter_mut().filter(|d| d.state != State::Active && !d.announced) {
+            download.announced = true;
+            ended.push(match &download.state {
+                State::Finished => format!("Downloaded {}", download.path.display()),
+                State::Failed(e) => format!("Download of {} failed: {}", file_name(&download.path), e),
+                _ => format!("Cancelled {}", file_name(&download.path)),
+            });
+        }
+        ended
+    }
+
+    // The about:downloads page, newest first
+    pub fn to_html(&self, notice: Option<&str>) -> String {
+        let list = self.list.lock().unwrap();
+        let mut html = String::from("<html><head><title>Downloads</title></head><body><h1>Downloads</h1>");
+        if let Some(notice) = notice {
+            html.push_str(&format!("<p><b>{}</b></p>", escape_html(notice)));
+        }
+        if list.is_empty() {
+            html.push_str("<p>No downloads.</p>");
+        }
+        html.push_str("<ul>");
+        for download in list.iter().rev() {
+            let id = download.id;
+            let name = escape_html(&file_name(&download.path));
+            let title = match (&download.state, Url::from_file_path(&download.path)) {
+                (State::Finished, Ok(file_url)) => format!("<a href=\"{}\">{}</a>", escape_html(file_url.as_str()), name),
+                _ => name,
+            };
+            let received = match download.total {
+                Some(total) => format!("{} of {}", format_bytes(download.received), format_bytes(total)),
+                None => format_bytes(download.received),
+            };
+            let state = match &download.state {
+                State::Active => {
+                    let seconds = download.started.elapsed().as_secs_f64().max(0.1);
+                    let rate = (download.received.saturating_sub(download.resumed_from) as f64 / seconds) as u64;
+                    format!(
+                        "{}, {}, {}/s &middot; <a href=\"{}?cancel={}\">Cancel</a>",
+                        escape_html(&progress_bar(download.received, download.total, 20)),
+                        received,
+                        format_bytes(rate),
+                        PAGE_URL,
+                        id
+                    )
+                }
+                State::Finished => format!("Finished, {}", format_bytes(download.received)),
+                State::Failed(e) => format!(
+                    "Failed after {}: {} &middot; <a href=\"{}?resume={}\">Resume</a> &middot; \
+                     <a href=\"{}?cancel={}\">Cancel</a>",
+                    received,
+                    escape_html(e),
+                    PAGE_URL,
+                    id,
+                    PAGE_URL,
+                    id
+                ),
+                State::Cancelled => format!("Cancelled &middot; <a href=\"{}?resume={}\">Start again</a>", PAGE_URL, id),
+            };
+            html.push_str(&format!(
+                "<li>{}<br>{}<br>{}<br>{}</li>",
+                title,
+                escape_html(&download.path.display().to_string()),
+                escape_html(&download.url),
+                state
+            ));
+        }
+        html.push_str("</ul>");
+        if list.iter().any(|d| d.state != State::Active) {
+            html.push_str(&format!("<p><a href=\"{}?clear\">Clear finished downloads</a></p>", PAGE_URL));
+        }
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+// What If-Range can check a resumed download against: a strong ETag, or
+// failing that Last-Modified
+fn validator(response: &blocking::Response) -> Option<String> {
+    http::header_text(response, ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| http::header_text(response, LAST_MODIFIED))
+        .filter(|value| !value.contains('\n') && HeaderValue::from_str(value).is_ok())
+}
+
+// GET `url` from `offset` on, as long as it still matches `validator`
+fn request(
+    client: &HttpClient,
+    url: &str,
+    offset: u64,
+    validator: Option<&str>,
+) -> Result<blocking::Response, FetchError> {
+    let mut headers = HeaderMap::new();
+    if let Some(validator) = validator.filter(|_| offset > 0) {
+        headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={}-", offset))?);
+        headers.insert(IF_RANGE, HeaderValue::from_str(validator)?);
+    }
+    http::send(client, &Request::get(url), headers)
+}
+
+// Fetch `url` into the .part file next to `path`, picking up where an
+// earlier attempt left off, then move it into place
+fn run(
+    list: &Mutex<Vec<Download>>,
+    id: usize,
+    client: &HttpClient,
+    url: &str,
+    path: &Path,
+    cancel: &AtomicBool,
+) -> Result<(), FetchError> {
+    let part = part_path(path);
+    let info = info_path(path);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    // Only a .part file from this URL, with a validator to check it by, is
+    // resumed; the server sends the whole file again if it has changed
+    let saved = fs::read_to_string(&info).ok().and_then(|text| {
+        let (saved_url, validator) = text.split_once('\n')?;
+        Some(validator.trim_end().to_string()).filter(|v| saved_url == url && !v.is_empty())
+    });
+    let mut offset = saved.as_ref().map_or(0, |_| fs::metadata(&part).map_or(0, |m| m.len()));
+    let mut response = request(client, url, offset, saved.as_deref())?;
+
+    // The range is past the end of the file, which must have shrunk; start
+    // over, once
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        offset = 0;
+        response = request(client, url, 0, None)?;
+    }
+    if !response.status().is_success() {
+        return Err(format!("the server replied {}", response.status()).into());
+    }
+    let resumed = offset > 0
+        && response.status() == StatusCode::PARTIAL_CONTENT
+        && http::header_text(&response, CONTENT_RANGE)
+            .is_some_and(|range| range.trim_start_matches("bytes ").starts_with(&format!("{}-", offset)));
+    let (mut file, start) = match resumed {
+        true => (OpenOptions::new().append(true).open(&part)?, offset),
+        false => {
+            let file = File::create(&part)?;
+            fs::write(&info, format!("{}\n{}\n", url, validator(&response).unwrap_or_default()))?;
+            (file, 0)
+        }
+    };
+
+    let total = response.content_length().map(|length| start + length);
+    if let Ok(download) = find(&mut list.lock().unwrap(), id) {
+        download.received = start;
+        download.total = total;
+        download.started = Instant::now();
+        download.resumed_from = start;
+    }
+
+    let mut received = start;
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("cancelled".into());
+        }
+        let n = response.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&chunk[..n])?;
+        received += n as u64;
+        if let Ok(download) = find(&mut list.lock().unwrap(), id) {
+            download.received = received;
+        }
+    }
+    file.flush()?;
+    drop(file);
+
+    if total.is_some_and(|total| received < total) {
+        return Err("the connection closed early".into());
+    }
+    if path.exists() {
+        return Err(format!("{} appeared while downloading; the download is kept as {}", path.display(), part.display()).into());
+    }
+    fs::rename(&part, path)?;
+    let _ = fs::remove_file(&info);
+    Ok(())
+}
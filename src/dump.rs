@@ -0,0 +1,44 @@
+// Non-interactive rendering of a page to stdout, in the spirit of `lynx -dump`.
+
+use std::io::{self, BufWriter, Write};
+
+use crate::config::Config;
+use crate::content::{self, ContentKind};
+use crate::http::{fetch_url, HttpClient};
+use crate::layout::layout;
+
+// Render a page to stdout without touching the terminal modes. Links are
+// marked with `[n]` and listed with their URLs at the end.
+pub fn dump_page(url: &str, width: usize, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let client = HttpClient::new(&config.fetch_options()).map_err(|e| e.to_string())?;
+    let response = fetch_url(url, &client).map_err(|e| e.to_string())?;
+    if content::kind(&response.content_type, &response.bytes) == ContentKind::Binary {
+        let mime = content::mime_type(&response.content_type);
+        return Err(format!("{} is a file of type {}, not a page", url, if mime.is_empty() { "unknown" } else { &mime }).into());
+    }
+    let document = content::render(&response, &config.keymap)?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = (|| -> io::Result<()> {
+        for line in layout(&document, width, true) {
+            writeln!(out, "{}", line.text().trim_end())?;
+        }
+
+        if !document.links.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "References")?;
+            writeln!(out)?;
+            for (i, link) in document.links.iter().enumerate() {
+                writeln!(out, "{:>4}. {}", i + 1, link.url)?;
+            }
+        }
+        out.flush()
+    })();
+
+    match result {
+        // The reader went away (e.g. `connex --dump url | head`); that's fine
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => Ok(other?),
+    }
+}
@@ -0,0 +1,399 @@
+// HTML forms: the fields of a page, how they are drawn in the page text and
+// how a filled-in form is encoded for submission.
+//
+// Every visible field is also a link of the document, so selecting, hinting
+// and numbering work for fields exactly as they do for links.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use url::form_urlencoded;
+use url::Url;
+
+use crate::document::Document;
+use crate::http::RequestBody;
+use crate::paths;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Enctype {
+    UrlEncoded,
+    Multipart,
+}
+
+pub struct Form {
+    // Where the form submits to, resolved against the page URL
+    pub action: String,
+    pub method: Method,
+    pub enctype: Enctype,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Password,
+    TextArea,
+    Hidden,
+    Checkbox,
+    Radio,
+    Select,
+    File,
+    Submit,
+    Reset,
+    Button,
+}
+
+pub struct Field {
+    pub kind: FieldKind,
+    // Index of the form it belongs to, if any
+    pub form: Option<usize>,
+    pub name: String,
+    // Text typed, a file path, or what a checkbox, radio button or button submits
+    pub value: String,
+    pub checked: bool,
+    // (label, value) of each option of a <select>, and the one chosen
+    pub options: Vec<(String, String)>,
+    pub selected: usize,
+    // Button text, or what to call a text field when editing it
+    pub label: String,
+    // Width of a text field in columns
+    pub size: usize,
+    pub disabled: bool,
+    // The link standing for the field on the page; hidden fields have none
+    pub link: Option<usize>,
+    // Value, checked state and selected option as loaded, for reset buttons
+    pub initial: (String, bool, usize),
+}
+
+// A form ready to send: GET forms carry their fields in the URL
+pub struct Submission {
+    pub url: String,
+    pub body: Option<RequestBody>,
+}
+
+impl Field {
+    pub fn is_text(&self) -> bool {
+        matches!(self.kind, FieldKind::Text | FieldKind::Password | FieldKind::TextArea)
+    }
+
+    // How the field appears in the page. Spaces are non-breaking so a field
+    // is never wrapped across lines.
+    pub fn widget(&self) -> String {
+        let text = match self.kind {
+            FieldKind::Text | FieldKind::File => format!("[{}]", pad(&self.value, self.size)),
+            FieldKind::Password => format!("[{}]", pad(&"*".repeat(self.value.chars().count()), self.size)),
+            FieldKind::TextArea => format!("[{}]", pad(&self.value.replace('\n', " "), self.size)),
+            FieldKind::Checkbox if self.checked => "[x]".to_string(),
+            FieldKind::Checkbox => "[ ]".to_string(),
+            FieldKind::Radio if self.checked => "(*)".to_string(),
+            FieldKind::Radio => "( )".to_string(),
+            FieldKind::Select => {
+                let label = self.options.get(self.selected).map_or("", |(label, _)| label.as_str());
+                format!("[{} ▾]", label)
+            }
+            FieldKind::Submit | FieldKind::Reset | FieldKind::Button => format!("[ {} ]", self.label),
+            FieldKind::Hidden => String::new(),
+        };
+        text.replace(' ', "\u{a0}")
+    }
+}
+
+// Fit text into `size` columns, filling the rest with underscores
+fn pad(text: &str, size: usize) -> String {
+    let mut padded: String = text.chars().take(size).collect();
+    let shown = padded.chars().count();
+    if shown < text.chars().count() && size > 0 {
+        padded.pop();
+        padded.push('…');
+    }
+    padded.extend(std::iter::repeat_n('_', size.saturating_sub(shown)));
+    padded
+}
+
+// Flip a checkbox, or check a radio button and clear the others in its group
+pub fn toggle(document: &mut Document, field: usize) {
+    let (kind, form, name) = {
+        let f = &document.fields[field];
+        (f.kind, f.form, f.name.clone())
+    };
+    match kind {
+        FieldKind::Checkbox => document.fields[field].checked ^= true,
+        FieldKind::Radio => {
+            for (i, f) in document.fields.iter_mut().enumerate() {
+                if f.kind == FieldKind::Radio && f.form == form && f.name == name {
+                    f.checked = i == field;
+                }
+            }
+        }
+        _ => return,
+    }
+    refresh_all(document);
+}
+
+// Put what was typed into a field. A <select> takes the option with that
+// label, or else the first one starting with it.
+pub fn set_value(document: &mut Document, field: usize, text: &str) -> Result<(), String> {
+    let f = &mut document.fields[field];
+    if f.kind == FieldKind::Select {
+        let lower = text.to_lowercase();
+        f.selected = f
+            .options
+            .iter()
+            .position(|(label, _)| label == text)
+            .or_else(|| f.options.iter().position(|(label, _)| label.to_lowercase().starts_with(&lower)))
+            .ok_or_else(|| format!("No option {}", text))?;
+    } else {
+        f.value = text.to_string();
+    }
+    document.refresh_field(field);
+    Ok(())
+}
+
+// Put every field of `form` back the way it was loaded
+pub fn reset(document: &mut Document, form: usize) {
+    for f in document.fields.iter_mut().filter(|f| f.form == Some(form)) {
+        let (value, checked, selected) = f.initial.clone();
+        f.value = value;
+        f.checked = checked;
+        f.selected = selected;
+    }
+    refresh_all(document);
+}
+
+fn refresh_all(document: &mut Document) {
+    for i in 0..document.fields.len() {
+        document.refresh_field(i);
+    }
+}
+
+// Enter in a text field submits its form when that's the only text field,
+// as in a search box; longer forms go through their submit button
+pub fn submits_on_enter(document: &Document, field: usize) -> bool {
+    let form = document.fields[field].form;
+    form.is_some() && document.fields.iter().filter(|f| f.form == form && f.is_text()).count() == 1
+}
+
+// The button that submits a form when Enter is pressed in one of its fields
+pub fn default_button(document: &Document, form: usize) -> Option<usize> {
+    document
+        .fields
+        .iter()
+        .position(|f| f.form == Some(form) && f.kind == FieldKind::Submit && !f.disabled)
+}
+
+enum Entry {
+    Text(String),
+    // Path of a file to upload
+    File(String),
+}
+
+// Encode the form `field` belongs to. A submit button submits itself along
+// with the rest of the form; any other field submits through the default
+// button, if there is one.
+pub fn submission(document: &Document, field: usize) -> Result<Submission, String> {
+    let form_index = document.fields[field].form.ok_or("Not part of a form")?;
+    let form = &document.forms[form_index];
+    let submitter = match document.fields[field].kind {
+        FieldKind::Submit => Some(field),
+        _ => default_button(document, form_index),
+    };
+
+    let mut entries = Vec::new();
+    for (i, f) in document.fields.iter().enumerate() {
+        if f.form != Some(form_index) || f.disabled || f.name.is_empty() {
+            continue;
+        }
+        let entry = match f.kind {
+            FieldKind::Checkbox | FieldKind::Radio if !f.checked => continue,
+            FieldKind::Submit if Some(i) != submitter => continue,
+            FieldKind::Reset | FieldKind::Button => continue,
+            FieldKind::Select => match f.options.get(f.selected) {
+                Some((_, value)) => Entry::Text(value.clone()),
+                None => continue,
+            },
+            FieldKind::File => Entry::File(f.value.clone()),
+            _ => Entry::Text(f.value.clone()),
+        };
+        entries.push((f.name.clone(), entry));
+    }
+
+    if form.method == Method::Get {
+        let mut url = Url::parse(&form.action).map_err(|e| format!("Invalid form action {}: {}", form.action, e))?;
+        url.set_query(Some(&url_encode(&entries)));
+        return Ok(Submission { url: url.to_string(), body: None });
+    }
+
+    let body = match form.enctype {
+        Enctype::UrlEncoded => RequestBody {
+            content_type: "application/x-www-form-urlencoded".to_string(),
+            bytes: url_encode(&entries).into_bytes(),
+        },
+        Enctype::Multipart => multipart(&entries)?,
+    };
+    Ok(Submission { url: form.action.clone(), body: Some(body) })
+}
+
+// Without multipart encoding only a file's name is sent, as browsers do
+fn url_encode(entries: &[(String, Entry)]) -> String {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (name, entry) in entries {
+        let value = match entry {
+            Entry::Text(text) => text.as_str(),
+            Entry::File(path) => file_name(path),
+        };
+        serializer.append_pair(name, &crlf(value));
+    }
+    serializer.finish()
+}
+
+// Line breaks as forms send them, whatever the text had
+fn crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n")
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// multipart/form-data, reading files to upload from disk
+fn multipart(entries: &[(String, Entry)]) -> Result<RequestBody, String> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let boundary = format!("----connex{:x}", nanos);
+    // Quotes and line breaks would end the header value early
+    let quote = |text: &str| text.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A");
+
+    let mut bytes = Vec::new();
+    for (name, entry) in entries {
+        bytes.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, quote(name)).as_bytes());
+        match entry {
+            Entry::Text(text) => {
+                bytes.extend_from_slice(b"\r\n\r\n");
+                bytes.extend_from_slice(crlf(text).as_bytes());
+            }
+            Entry::File(path) => {
+                let contents = match path.as_str() {
+                    "" => Vec::new(),
+                    path => {
+                        std::fs::read(paths::expand_home(path)).map_err(|e| format!("Cannot read {}: {}", path, e))?
+                    }
+                };
+                let header = format!(
+                    "; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                    quote(file_name(path))
+                );
+                bytes.extend_from_slice(header.as_bytes());
+                bytes.extend_from_slice(&contents);
+            }
+        }
+        bytes.extend_from_slice(b"\r\n");
+    }
+    bytes.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    Ok(RequestBody { content_type: format!("multipart/form-data; boundary={}", boundary), bytes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::parse_html;
+
+    fn page(html: &str) -> Document {
+        parse_html(html, &Url::parse("http://example.com/dir/page").unwrap())
+    }
+
+    fn field(document: &Document, name: &str) -> usize {
+        document.fields.iter().position(|f| f.name == name).unwrap()
+    }
+
+    const FORM: &str = r#"<form action="/search" method="get">
+        <input name="q" value="a b&c">
+        <textarea name="t">one
+two</textarea>
+        <input type="checkbox" name="on" checked><input type="checkbox" name="off">
+        <input type="radio" name="r" value="1"><input type="radio" name="r" value="2" checked>
+        <select name="s"><option value="x">X</option><option value="y" selected>Y</option></select>
+        <input name="gone" value="1" disabled><input value="nameless">
+        <input type="hidden" name="h" value="secret">
+        <input type="submit" name="go" value="Go"><input type="submit" name="other" value="Other">
+        <input type="reset" name="reset">
+        </form>"#;
+
+    #[test]
+    fn url_encoded_get() {
+        let document = page(FORM);
+        let submission = submission(&document, field(&document, "q")).unwrap();
+        assert_eq!(
+            submission.url,
+            "http://example.com/search?q=a+b%26c&t=one%0D%0Atwo&on=on&r=2&s=y&h=secret&go=Go"
+        );
+        assert!(submission.body.is_none());
+
+        // A button submits itself rather than the default one
+        let submission = super::submission(&document, field(&document, "other")).unwrap();
+        assert!(submission.url.ends_with("&h=secret&other=Other"));
+    }
+
+    #[test]
+    fn line_breaks_become_crlf() {
+        let entries = vec![
+            ("a".to_string(), Entry::Text("1\n2".to_string())),
+            ("b".to_string(), Entry::Text("1\r\n2\r3".to_string())),
+            ("f".to_string(), Entry::File("/tmp/dir/report.txt".to_string())),
+        ];
+        assert_eq!(url_encode(&entries), "a=1%0D%0A2&b=1%0D%0A2%0D%0A3&f=report.txt");
+    }
+
+    #[test]
+    fn url_encoded_post() {
+        let mut document = page(r#"<form method="post" action="post"><input name="n" value="é &"></form>"#);
+        set_value(&mut document, 0, "é & ü").unwrap();
+        let submission = submission(&document, 0).unwrap();
+        assert_eq!(submission.url, "http://example.com/dir/post");
+        let body = submission.body.unwrap();
+        assert_eq!(body.content_type, "application/x-www-form-urlencoded");
+        assert_eq!(String::from_utf8(body.bytes).unwrap(), "n=%C3%A9+%26+%C3%BC");
+    }
+
+    #[test]
+    fn multipart_post() {
+        let dir = std::env::temp_dir().join(format!("connex-form-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("up\"load.txt");
+        std::fs::write(&path, b"file\x00contents").unwrap();
+
+        let mut document = page(
+            r#"<form method="post" enctype="multipart/form-data" action="/up">
+            <input name="title" value="hi"><input type="file" name="doc"><input type="file" name="none">
+            </form>"#,
+        );
+        let doc = field(&document, "doc");
+        set_value(&mut document, doc, path.to_str().unwrap()).unwrap();
+        set_value(&mut document, 0, "hi\nthere").unwrap();
+        let body = submission(&document, 0).unwrap().body.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let boundary = body.content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhi\r\nthere\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"doc\"; filename=\"up%22load.txt\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\nfile\x00contents\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"none\"; filename=\"\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(body.bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn unreadable_upload() {
+        let mut document = page(r#"<form method="post" enctype="multipart/form-data"><input type="file" name="f">"#);
+        set_value(&mut document, 0, "/nonexistent/connex-test").unwrap();
+        let error = submission(&document, 0).err().unwrap();
+        assert!(error.starts_with("Cannot read /nonexistent/connex-test"));
+    }
+}
@@ -0,0 +1,87 @@
+// Link hints: short labels over every link on screen, so a link can be
+// picked by typing its label instead of tabbing to it.
+
+use crate::layout::Line;
+use crate::motion;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HintAction {
+    Follow,
+    FollowInNewTab,
+    CopyUrl,
+}
+
+pub enum HintResult {
+    Picked(usize),
+    Continue,
+    NoMatch,
+}
+
+pub struct Hints {
+    pub action: HintAction,
+    // (label, link) for each link on screen, top to bottom
+    labels: Vec<(String, usize)>,
+    pub typed: String,
+}
+
+// `count` labels from `chars`, all the same length and as short as possible
+fn make_labels(chars: &[char], count: usize) -> Vec<String> {
+    let mut length = 1;
+    while chars.len().pow(length) < count {
+        length += 1;
+    }
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![chars[0]; length as usize];
+            for slot in label.iter_mut().rev() {
+                *slot = chars[n % chars.len()];
+                n /= chars.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+impl Hints {
+    // Label the links on lines `top..top + height`; None if there are none
+    pub fn new(action: HintAction, lines: &[Line], top: usize, height: usize, chars: &str) -> Option<Self> {
+        let links = motion::visible_links(lines, top, height);
+        if links.is_empty() {
+            return None;
+        }
+
+        let chars: Vec<char> = chars.chars().collect();
+        let labels = make_labels(&chars, links.len()).into_iter().zip(links).collect();
+        Some(Hints { action, labels, typed: String::new() })
+    }
+
+    // What is left to type of the label of `link`, if it still matches
+    pub fn label(&self, link: usize) -> Option<&str> {
+        let (label, _) = self.labels.iter().find(|(_, l)| *l == link)?;
+        label.strip_prefix(self.typed.as_str())
+    }
+
+    pub fn type_char(&mut self, c: char) -> HintResult {
+        self.typed.push(c);
+        let mut matching = self.labels.iter().filter(|(label, _)| label.starts_with(&self.typed));
+        match matching.next() {
+            Some((label, link)) if *label == self.typed => HintResult::Picked(*link),
+            Some(_) => HintResult::Continue,
+            None => HintResult::NoMatch,
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.typed.pop();
+    }
+
+    // Shown on the bottom line while hints are up
+    pub fn prompt(&self) -> String {
+        let verb = match self.action {
+            HintAction::Follow => "Follow link",
+            HintAction::FollowInNewTab => "Open link in new tab",
+            HintAction::CopyUrl => "Copy link URL",
+        };
+        format!("{}: {}", verb, self.typed)
+    }
+}
@@ -0,0 +1,76 @@
+// Back/forward navigation history for a browsing session.
+//
+// Each entry remembers where the user was on the page (scroll position and
+// selected link) so that going back or forward puts them where they left off.
+
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub scroll_offset: u16,
+    pub selected_link_idx: Option<usize>,
+}
+
+impl HistoryEntry {
+    pub fn new(url: String) -> Self {
+        HistoryEntry { url, scroll_offset: 0, selected_link_idx: None }
+    }
+}
+
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    index: usize,
+}
+
+impl History {
+    pub fn new(start_url: String) -> Self {
+        History { entries: vec![HistoryEntry::new(start_url)], index: 0 }
+    }
+
+    pub fn current(&self) -> &HistoryEntry {
+        &self.entries[self.index]
+    }
+
+    // Record the view state of the current page before leaving it
+    pub fn save_position(&mut self, scroll_offset: u16, selected_link_idx: Option<usize>) {
+        let entry = &mut self.entries[self.index];
+        entry.scroll_offset = scroll_offset;
+        entry.selected_link_idx = selected_link_idx;
+    }
+
+    // Visiting a new page drops any forward entries, like every browser does
+    pub fn push(&mut self, url: String) {
+        self.entries.truncate(self.index + 1);
+        self.entries.push(HistoryEntry::new(url));
+        self.index = self.entries.len() - 1;
+    }
+
+    // Swap the current page for another without adding an entry
+    pub fn replace(&mut self, url: String) {
+        self.entries[self.index] = HistoryEntry::new(url);
+    }
+
+    // Where back/forward would go, without going there
+    pub fn peek_back(&self) -> Option<&HistoryEntry> {
+        self.index.checked_sub(1).map(|i| &self.entries[i])
+    }
+
+    pub fn peek_forward(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.index + 1)
+    }
+
+    pub fn back(&mut self) -> Option<&HistoryEntry> {
+        if self.index == 0 {
+            return None;
+        }
+        self.index -= 1;
+        Some(&self.entries[self.index])
+    }
+
+    pub fn forward(&mut self) -> Option<&HistoryEntry> {
+        if self.index + 1 >= self.entries.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(&self.entries[self.index])
+    }
+}
@@ -0,0 +1,251 @@
+// Fetching pages over HTTP(S) or from local files.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::blocking::{self, Client};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_LANGUAGE, CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE,
+};
+use reqwest::{redirect, Proxy};
+use url::Url;
+
+use crate::content::{self, ContentKind};
+use crate::cookies::{self, CookieJar};
+
+pub type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+// How to make requests, from the config file and command line
+#[derive(Clone)]
+pub struct FetchOptions {
+    pub user_agent: String,
+    pub accept_language: Option<String>,
+    // Sent to a domain and its subdomains, or everywhere for "*"
+    pub headers: Vec<(String, HeaderMap)>,
+    // None waits forever
+    pub connect_timeout: Option<Duration>,
+    // How long to wait for the response to start, then for each chunk of it
+    pub read_timeout: Option<Duration>,
+    pub max_redirects: usize,
+    // http://, https://, socks5:// or socks5h:// URL; None uses the
+    // environment's proxy settings, if any
+    pub proxy: Option<String>,
+}
+
+// One client for the whole session, so connections are kept open and
+// reused. Cloning it shares the connections.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    headers: Vec<(String, HeaderMap)>,
+    max_redirects: usize,
+    // None neither sends nor keeps cookies
+    cookies: Option<Arc<CookieJar>>,
+}
+
+impl HttpClient {
+    pub fn new(options: &FetchOptions) -> Result<Self, FetchError> {
+        let mut default_headers = HeaderMap::new();
+        if let Some(language) = &options.accept_language {
+            default_headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_str(language)?);
+        }
+        // Redirects are followed by hand so every hop gets and sets cookies
+        let mut builder = Client::builder()
+            .user_agent(&options.user_agent)
+            .default_headers(default_headers)
+            .connect_timeout(options.connect_timeout)
+            .timeout(options.read_timeout)
+            .redirect(redirect::Policy::none());
+        if let Some(proxy) = &options.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        Ok(HttpClient {
+            client: builder.build()?,
+            headers: options.headers.clone(),
+            max_redirects: options.max_redirects,
+            cookies: None,
+        })
+    }
+
+    pub fn with_cookies(self, cookies: Arc<CookieJar>) -> Self {
+        HttpClient { cookies: Some(cookies), ..self }
+    }
+
+    // The configured headers for `url`, more specific domains overriding
+    // "*" and their parent domains
+    fn headers_for(&self, url: &Url) -> HeaderMap {
+        let host = url.host_str().unwrap_or("");
+        let mut matching: Vec<&(String, HeaderMap)> = self
+            .headers
+            .iter()
+            .filter(|(domain, _)| domain == "*" || cookies::domain_match(host, domain))
+            .collect();
+        matching.sort_by_key(|(domain, _)| if domain == "*" { 0 } else { domain.len() + 1 });
+        let mut headers = HeaderMap::new();
+        for (_, extra) in matching {
+            for (name, value) in extra {
+                headers.insert(name, value.clone());
+            }
+        }
+        headers
+    }
+}
+
+// Body of a POST request, such as an encoded form
+#[derive(Clone)]
+pub struct RequestBody {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Request {
+    pub url: String,
+    // Makes it a POST
+    pub body: Option<RequestBody>,
+    // The page whose link or form led here, which decides whether SameSite
+    // cookies go along
+    pub initiator: Option<String>,
+}
+
+impl Request {
+    pub fn get(url: &str) -> Self {
+        Request { url: url.to_string(), body: None, initiator: None }
+    }
+}
+
+pub struct Response {
+    // Final URL after redirects; relative links resolve against it
+    pub url: String,
+    // As the server sent it, charset and all; empty if it didn't say
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+    // Files to save rather than show come without their bytes
+    pub download: Option<DownloadOffer>,
+}
+
+pub struct DownloadOffer {
+    pub url: String,
+    // From Content-Disposition, or else the URL
+    pub file_name: String,
+    pub size: Option<u64>,
+}
+
+impl Response {
+    // A page made up locally
+    pub fn html(url: String, html: String) -> Self {
+        Response { url, content_type: "text/html; charset=utf-8".to_string(), bytes: html.into_bytes(), download: None }
+    }
+}
+
+// Content types of local files, by extension; others are sniffed
+fn file_content_type(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("html" | "htm" | "xhtml") => "text/html",
+        Some("txt" | "text" | "md") => "text/plain",
+        _ => "",
+    }
+}
+
+// Make `request`, reporting (bytes received, expected total) as data
+// arrives. Setting `cancel` aborts the transfer at the next chunk.
+pub fn fetch(
+    client: &HttpClient,
+    request: &Request,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Response, FetchError> {
+    let url = Url::parse(&request.url)?;
+    // Local files given on the command line are read straight from disk
+    if url.scheme() == "file" {
+        let path = url.to_file_path().map_err(|_| format!("invalid file URL: {}", url))?;
+        let bytes = std::fs::read(&path)?;
+        let content_type = file_content_type(&path).to_string();
+        return Ok(Response { url: url.to_string(), content_type, bytes, download: None });
+    }
+
+    let mut response = send(client, request, HeaderMap::new())?;
+    let final_url = response.url().to_string();
+    let total = response.content_length();
+    let content_type = header_text(&response, CONTENT_TYPE).unwrap_or_default();
+
+    // Files are left for the download manager to fetch, rather than read
+    // into memory here. Without a Content-Type the body is sniffed instead.
+    // The manager fetches with a GET, so the answer to a form post is read
+    // here all the same, to be shown or saved from the page.
+    let disposition = header_text(&response, CONTENT_DISPOSITION);
+    let attachment = disposition.as_deref().is_some_and(|d| d.trim_start().to_ascii_lowercase().starts_with("attachment"));
+    let binary = !content_type.is_empty() && content::kind(&content_type, &[]) == ContentKind::Binary;
+    if request.body.is_none() && (attachment || binary) {
+        let file_name = disposition
+            .as_deref()
+            .and_then(content::disposition_file_name)
+            .unwrap_or_else(|| content::file_name(&final_url));
+        let download = DownloadOffer { url: final_url.clone(), file_name, size: total };
+        return Ok(Response { url: final_url, content_type, bytes: Vec::new(), download: Some(download) });
+    }
+
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("cancelled".into());
+        }
+        let n = response.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..n]);
+        progress(bytes.len() as u64, total);
+    }
+
+    Ok(Response { url: final_url, content_type, bytes, download: None })
+}
+
+pub fn header_text(response: &blocking::Response, name: HeaderName) -> Option<String> {
+    response.headers().get(name).map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+// Send `request` with `headers` added, following redirects and keeping
+// cookies along the way. The body is left to read.
+pub fn send(client: &HttpClient, request: &Request, headers: HeaderMap) -> Result<blocking::Response, FetchError> {
+    let mut url = Url::parse(&request.url)?;
+    let initiator = request.initiator.as_deref().and_then(|u| Url::parse(u).ok());
+    let mut body = request.body.as_ref();
+    let mut redirects = 0;
+    loop {
+        let mut builder = match body {
+            Some(body) => client.client.post(url.clone()).header(CONTENT_TYPE, &body.content_type).body(body.bytes.clone()),
+            None => client.client.get(url.clone()),
+        };
+        builder = builder.headers(client.headers_for(&url)).headers(headers.clone());
+        let jar = client.cookies.as_deref();
+        if let Some(cookies) = jar.and_then(|jar| jar.header(&url, initiator.as_ref(), body.is_none())) {
+            builder = builder.header(COOKIE, cookies);
+        }
+        let response = builder.send()?;
+        if let Some(jar) = jar {
+            for header in response.headers().get_all(SET_COOKIE) {
+                jar.store(&url, &String::from_utf8_lossy(header.as_bytes()));
+            }
+        }
+
+        let location = response.headers().get(LOCATION).and_then(|l| l.to_str().ok());
+        let Some(location) = location.filter(|_| response.status().is_redirection()) else { return Ok(response) };
+        redirects += 1;
+        if redirects > client.max_redirects {
+            return Err("too many redirects".into());
+        }
+        url = url.join(location)?;
+        // Only 307 and 308 repeat a POST; the rest turn it into a GET
+        if !matches!(response.status().as_u16(), 307 | 308) {
+            body = None;
+        }
+    }
+}
+
+// Blocking fetch without progress reporting, for non-interactive use
+pub fn fetch_url(url: &str, client: &HttpClient) -> Result<Response, FetchError> {
+    fetch(client, &Request::get(url), &AtomicBool::new(false), |_, _| {})
+}
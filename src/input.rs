@@ -0,0 +1,228 @@
+// Single-line text editor used by the prompts at the bottom of the screen.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+pub enum EditResult {
+    // The user pressed Enter
+    Submit(String),
+    // The user pressed Esc (or Ctrl-C)
+    Cancel,
+    // Anything else; the buffer may or may not have changed
+    Continue,
+}
+
+pub struct LineEditor {
+    pub prompt: String,
+    buffer: Vec<char>,
+    // Shown as asterisks, for passwords
+    masked: bool,
+    // Holds line breaks, typed with Alt-Enter and shown as ↵, for text areas
+    multiline: bool,
+    cursor: usize,
+    // Previous entries, oldest first, recalled with Up/Down
+    history: Vec<String>,
+    history_pos: Option<usize>,
+    // Text typed before recalling history, restored when walking past the newest entry
+    draft: String,
+    // Tab completion state: the prefix being completed and the last candidate offered
+    completion: Option<(String, usize)>,
+}
+
+impl LineEditor {
+    pub fn new(prompt: &str, initial: &str, history: Vec<String>) -> Self {
+        let buffer: Vec<char> = initial.chars().collect();
+        LineEditor {
+            prompt: prompt.to_string(),
+            cursor: buffer.len(),
+            buffer,
+            masked: false,
+            multiline: false,
+            history,
+            history_pos: None,
+            draft: String::new(),
+            completion: None,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    pub fn masked(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    pub fn multiline(mut self) -> Self {
+        self.multiline = true;
+        self
+    }
+
+    // The text as it appears on screen
+    pub fn shown_text(&self) -> String {
+        match self.masked {
+            true => "*".repeat(self.buffer.len()),
+            false => self.text().replace('\n', "↵"),
+        }
+    }
+
+    // Cursor column relative to the start of the prompt
+    pub fn cursor_column(&self) -> usize {
+        self.prompt.chars().count() + self.cursor
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.buffer = text.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, completions: &[String]) -> EditResult {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        if key.code != KeyCode::Tab {
+            self.completion = None;
+        }
+
+        match key.code {
+            KeyCode::Enter if alt && self.multiline => {
+                self.buffer.insert(self.cursor, '\n');
+                self.cursor += 1;
+            }
+            KeyCode::Enter => return EditResult::Submit(self.text()),
+            KeyCode::Esc => return EditResult::Cancel,
+            KeyCode::Char('c') if ctrl => return EditResult::Cancel,
+
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.buffer.len(),
+            KeyCode::Char('b') if ctrl => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('f') if ctrl => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.buffer.len(),
+
+            KeyCode::Backspace => self.delete_before_cursor(),
+            KeyCode::Char('h') if ctrl => self.delete_before_cursor(),
+            KeyCode::Delete => self.delete_at_cursor(),
+            KeyCode::Char('d') if ctrl => self.delete_at_cursor(),
+            KeyCode::Char('u') if ctrl => {
+                self.buffer.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char('k') if ctrl => self.buffer.truncate(self.cursor),
+            KeyCode::Char('w') if ctrl => {
+                // Delete the word before the cursor
+                let mut start = self.cursor;
+                while start > 0 && self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.buffer.drain(start..self.cursor);
+                self.cursor = start;
+            }
+
+            KeyCode::Up => self.recall_older(),
+            KeyCode::Down => self.recall_newer(),
+            KeyCode::Tab => self.complete(completions),
+
+            KeyCode::Char(c) if !ctrl => {
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+
+        EditResult::Continue
+    }
+
+    fn delete_before_cursor(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn delete_at_cursor(&mut self) {
+        if self.cursor < self.buffer.len() {
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn recall_older(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let pos = match self.history_pos {
+            None => {
+                self.draft = self.text();
+                self.history.len() - 1
+            }
+            Some(0) => 0,
+            Some(i) => i - 1,
+        };
+        self.history_pos = Some(pos);
+        let entry = self.history[pos].clone();
+        self.set_text(&entry);
+    }
+
+    fn recall_newer(&mut self) {
+        match self.history_pos {
+            None => {}
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_pos = Some(i + 1);
+                let entry = self.history[i + 1].clone();
+                self.set_text(&entry);
+            }
+            Some(_) => {
+                self.history_pos = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.set_text(&draft);
+            }
+        }
+    }
+
+    // Cycle through the candidates containing what the user typed, preferring
+    // ones that start with it
+    fn complete(&mut self, candidates: &[String]) {
+        let (prefix, last) = match self.completion.take() {
+            Some((prefix, last)) => (prefix, Some(last)),
+            None => (self.text(), None),
+        };
+
+        let mut matches: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| completion_key(c).starts_with(&prefix) || c.starts_with(&prefix))
+            .map(|(i, _)| i)
+            .collect();
+        if matches.is_empty() {
+            matches = candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.contains(&prefix))
+                .map(|(i, _)| i)
+                .collect();
+        }
+        if matches.is_empty() {
+            return;
+        }
+
+        let next = match last.and_then(|l| matches.iter().position(|&m| m == l)) {
+            Some(pos) => matches[(pos + 1) % matches.len()],
+            None => matches[0],
+        };
+        self.set_text(&candidates[next]);
+        self.completion = Some((prefix, next));
+    }
+}
+
+// Let "en.wiki" complete to "https://en.wikipedia.org/..." without typing the scheme
+fn completion_key(candidate: &str) -> &str {
+    candidate
+        .split_once("://")
+        .map(|(_, rest)| rest.strip_prefix("www.").unwrap_or(rest))
+        .unwrap_or(candidate)
+}
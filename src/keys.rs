@@ -0,0 +1,707 @@
+// Key bindings: sequences of key chords mapped to named actions, starting
+// from a built-in preset and adjusted by the config file.
+//
+// Keys are written as in Vim: plain characters stand for themselves and
+// anything else goes in angle brackets, e.g. "gg", "<C-f>", "<A-1>",
+// "<C-x>k", "<Enter>", "<S-Tab>", "<lt>" for a literal "<" and "<A-gt>" for
+// Alt with ">".
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::document::escape_html;
+
+pub const PAGE_URL: &str = "about:keys";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Help,
+    NextLink,
+    PreviousLink,
+    LinkUp,
+    LinkDown,
+    LinkLeft,
+    LinkRight,
+    FollowLink,
+    FollowLinkInNewTab,
+    HintFollow,
+    HintFollowInNewTab,
+    HintCopyUrl,
+    ToggleLinkNumbers,
+    Back,
+    Forward,
+    Reload,
+    SavePage,
+    Open,
+    EditUrl,
+    OpenInNewTab,
+    NextTab,
+    PreviousTab,
+    // Zero-based
+    GoToTab(usize),
+    CloseTab,
+    SearchForward,
+    SearchBackward,
+    NextMatch,
+    PreviousMatch,
+    Cancel,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    ScrollLeft,
+    ScrollRight,
+    Top,
+    Bottom,
+    NextParagraph,
+    PreviousParagraph,
+    NextHeading,
+    PreviousHeading,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    Bookmark,
+    Bookmarks,
+    EditBookmark,
+    DeleteBookmark,
+    Filter,
+    History,
+    Downloads,
+}
+
+// Every action with its name in the config file and its help text, in the
+// order the help page lists them
+const ACTIONS: &[(Action, &str, &str)] = &[
+    (Action::ScrollDown, "scroll-down", "Scroll down a line"),
+    (Action::ScrollUp, "scroll-up", "Scroll up a line"),
+    (Action::PageDown, "page-down", "Scroll down a page"),
+    (Action::PageUp, "page-up", "Scroll up a page"),
+    (Action::HalfPageDown, "half-page-down", "Scroll down half a page"),
+    (Action::HalfPageUp, "half-page-up", "Scroll up half a page"),
+    (Action::ScrollLeft, "scroll-left", "Scroll left"),
+    (Action::ScrollRight, "scroll-right", "Scroll right"),
+    (Action::Top, "top", "Go to the top of the page, or to line N with a count"),
+    (Action::Bottom, "bottom", "Go to the bottom of the page, or to line N with a count"),
+    (Action::NextParagraph, "next-paragraph", "Scroll to the next paragraph"),
+    (Action::PreviousParagraph, "previous-paragraph", "Scroll to the previous paragraph"),
+    (Action::NextHeading, "next-heading", "Scroll to the next heading"),
+    (Action::PreviousHeading, "previous-heading", "Scroll to the previous heading"),
+    (Action::ScreenTop, "screen-top", "Select the first link on the screen"),
+    (Action::ScreenMiddle, "screen-middle", "Select the link nearest the middle of the screen"),
+    (Action::ScreenBottom, "screen-bottom", "Select the last link on the screen"),
+    (Action::NextLink, "next-link", "Select the next link"),
+    (Action::PreviousLink, "previous-link", "Select the previous link"),
+    (Action::LinkUp, "link-up", "Select the nearest link above"),
+    (Action::LinkDown, "link-down", "Select the nearest link below"),
+    (Action::LinkLeft, "link-left", "Select the nearest link to the left"),
+    (Action::LinkRight, "link-right", "Select the nearest link to the right"),
+    (Action::FollowLink, "follow-link", "Follow the selected link, or link N with a count"),
+    (Action::FollowLinkInNewTab, "follow-link-in-new-tab", "Open the selected link, or link N with a count, in a new tab"),
+    (Action::HintFollow, "hint-follow", "Label the links on screen and follow the one typed"),
+    (Action::HintFollowInNewTab, "hint-follow-in-new-tab", "Label the links on screen and open the one typed in a new tab"),
+    (Action::HintCopyUrl, "hint-copy-url", "Label the links on screen and copy the URL of the one typed"),
+    (Action::ToggleLinkNumbers, "toggle-link-numbers", "Show or hide [n] numbers before links"),
+    (Action::Back, "back", "Go back"),
+    (Action::Forward, "forward", "Go forward"),
+    (Action::Reload, "reload", "Reload the page"),
+    (Action::SavePage, "save-page", "Save the page, or a file that can't be shown, to disk"),
+    (Action::Open, "open", "Open a URL or search the web"),
+    (Action::EditUrl, "edit-url", "Edit the current URL"),
+    (Action::OpenInNewTab, "open-in-new-tab", "Open a URL or search in a new tab"),
+    (Action::NextTab, "next-tab", "Switch to the next tab, or to tab N with a count"),
+    (Action::PreviousTab, "previous-tab", "Switch to the previous tab"),
+    (Action::GoToTab(0), "tab-1", "Switch to tab 1"),
+    (Action::GoToTab(1), "tab-2", "Switch to tab 2"),
+    (Action::GoToTab(2), "tab-3", "Switch to tab 3"),
+    (Action::GoToTab(3), "tab-4", "Switch to tab 4"),
+    (Action::GoToTab(4), "tab-5", "Switch to tab 5"),
+    (Action::GoToTab(5), "tab-6", "Switch to tab 6"),
+    (Action::GoToTab(6), "tab-7", "Switch to tab 7"),
+    (Action::GoToTab(7), "tab-8", "Switch to tab 8"),
+    (Action::GoToTab(8), "tab-9", "Switch to tab 9"),
+    (Action::CloseTab, "close-tab", "Close the tab"),
+    (Action::SearchForward, "search-forward", "Find text in the page"),
+    (Action::SearchBackward, "search-backward", "Find text in the page, searching upwards"),
+    (Action::NextMatch, "next-match", "Go to the next match"),
+    (Action::PreviousMatch, "previous-match", "Go to the previous match"),
+    (Action::Cancel, "cancel", "Stop loading, or clear search highlights"),
+    (Action::Bookmark, "bookmark", "Bookmark the current page"),
+    (Action::Bookmarks, "bookmarks", "List bookmarks"),
+    (Action::EditBookmark, "edit-bookmark", "Edit the selected bookmark on the bookmarks page"),
+    (Action::DeleteBookmark, "delete-bookmark", "Delete the selected bookmark on the bookmarks page"),
+    (Action::History, "history", "List pages visited"),
+    (Action::Downloads, "downloads", "List downloads"),
+    (Action::Filter, "filter", "Filter the bookmarks or history page"),
+    (Action::Help, "help", "List key bindings"),
+    (Action::Quit, "quit", "Quit connex"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|(_, n, _)| *n == name).map(|(action, _, _)| *action)
+    }
+}
+
+// One key press with its modifiers. Shift is folded into the character for
+// printable keys, so "G" is just 'G'.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        // Terminals report Ctrl-letters in lower case
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        Key { code, modifiers }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Key::new(event.code, event.modifiers)
+    }
+
+    // The value of an unmodified digit key, for counts
+    pub fn digit(&self) -> Option<usize> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => c.to_digit(10).map(|d| d as usize),
+            _ => None,
+        }
+    }
+}
+
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Enter", KeyCode::Enter),
+    ("CR", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("BS", KeyCode::Backspace),
+    ("Backspace", KeyCode::Backspace),
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("gt", KeyCode::Char('>')),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("Insert", KeyCode::Insert),
+    ("Del", KeyCode::Delete),
+    ("Delete", KeyCode::Delete),
+];
+
+// The inside of "<...>": modifier prefixes, then a key name or character
+fn parse_chord(chord: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = chord;
+    while let Some((prefix, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty()) {
+        modifiers |= match prefix.to_ascii_uppercase().as_str() {
+            "C" => KeyModifiers::CONTROL,
+            "A" | "M" => KeyModifiers::ALT,
+            "S" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = tail;
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match KEY_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(rest)) {
+            Some((_, code)) => *code,
+            None => {
+                let number = rest.strip_prefix(['F', 'f'])?.parse().ok().filter(|n| (1..=12).contains(n))?;
+                KeyCode::F(number)
+            }
+        },
+    };
+    // <S-Tab> is what terminals send as BackTab
+    if code == KeyCode::Tab && modifiers.contains(KeyModifiers::SHIFT) {
+        return Some(Key::new(KeyCode::BackTab, modifiers));
+    }
+    Some(Key::new(code, modifiers))
+}
+
+// Parse a key sequence such as "gg" or "<C-x>k"
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>').filter(|&end| end > 1) {
+                let chord = &rest[1..end];
+                keys.push(parse_chord(chord).ok_or_else(|| format!("unknown key <{}> in \"{}\"", chord, text))?);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        keys.push(Key::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+// Write keys back in the notation parse_keys reads
+pub fn format_keys(keys: &[Key]) -> String {
+    let mut text = String::new();
+    for key in keys {
+        let mut prefix = String::new();
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if key.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("A-");
+        }
+        if key.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+        let name = match key.code {
+            KeyCode::Char('<') => "lt".to_string(),
+            KeyCode::Char('>') if !prefix.is_empty() => "gt".to_string(),
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::BackTab => "S-Tab".to_string(),
+            code => KEY_NAMES.iter().find(|(_, c)| *c == code).map_or("?", |(name, _)| name).to_string(),
+        };
+        if prefix.is_empty() && name.chars().count() == 1 {
+            text.push_str(&name);
+        } else {
+            text.push_str(&format!("<{}{}>", prefix, name));
+        }
+    }
+    text
+}
+
+// Built-in binding sets, as (keys, action name) pairs
+const DEFAULT_PRESET: &[(&str, &str)] = &[
+    ("<Down>", "scroll-down"),
+    ("<Up>", "scroll-up"),
+    ("<PageDown>", "page-down"),
+    ("<PageUp>", "page-up"),
+    ("j", "scroll-down"),
+    ("k", "scroll-up"),
+    ("<C-d>", "half-page-down"),
+    ("<C-u>", "half-page-up"),
+    ("<Left>", "scroll-left"),
+    ("<Right>", "scroll-right"),
+    ("<Home>", "top"),
+    ("<End>", "bottom"),
+    ("gg", "top"),
+    ("G", "bottom"),
+    ("H", "screen-top"),
+    ("M", "screen-middle"),
+    ("L", "screen-bottom"),
+    ("}", "next-paragraph"),
+    ("{", "previous-paragraph"),
+    ("<A-Down>", "next-heading"),
+    ("<A-Up>", "previous-heading"),
+    ("<Tab>", "next-link"),
+    ("<S-Tab>", "previous-link"),
+    ("<S-Up>", "link-up"),
+    ("<S-Down>", "link-down"),
+    ("<S-Left>", "link-left"),
+    ("<S-Right>", "link-right"),
+    ("<Enter>", "follow-link"),
+    ("t", "follow-link-in-new-tab"),
+    ("f", "hint-follow"),
+    ("F", "hint-follow-in-new-tab"),
+    ("#", "toggle-link-numbers"),
+    ("yf", "hint-copy-url"),
+    ("<BS>", "back"),
+    ("h", "back"),
+    ("l", "forward"),
+    ("r", "reload"),
+    ("s", "save-page"),
+    ("o", "open"),
+    ("go", "edit-url"),
+    ("O", "open-in-new-tab"),
+    ("]", "next-tab"),
+    ("[", "previous-tab"),
+    ("<A-1>", "tab-1"),
+    ("<A-2>", "tab-2"),
+    ("<A-3>", "tab-3"),
+    ("<A-4>", "tab-4"),
+    ("<A-5>", "tab-5"),
+    ("<A-6>", "tab-6"),
+    ("<A-7>", "tab-7"),
+    ("<A-8>", "tab-8"),
+    ("<A-9>", "tab-9"),
+    ("x", "close-tab"),
+    ("/", "search-forward"),
+    ("<C-r>", "search-backward"),
+    ("n", "next-match"),
+    ("N", "previous-match"),
+    ("<Esc>", "cancel"),
+    ("b", "bookmark"),
+    ("B", "bookmarks"),
+    ("e", "edit-bookmark"),
+    ("d", "delete-bookmark"),
+    ("gh", "history"),
+    ("D", "downloads"),
+    ("=", "filter"),
+    ("?", "help"),
+    ("<F1>", "help"),
+    ("q", "quit"),
+];
+
+const VI_PRESET: &[(&str, &str)] = &[
+    ("j", "scroll-down"),
+    ("<Down>", "scroll-down"),
+    ("k", "scroll-up"),
+    ("<Up>", "scroll-up"),
+    ("<C-f>", "page-down"),
+    ("<Space>", "page-down"),
+    ("<PageDown>", "page-down"),
+    ("<C-b>", "page-up"),
+    ("<PageUp>", "page-up"),
+    ("<C-e>", "scroll-down"),
+    ("<C-y>", "scroll-up"),
+    ("<C-d>", "half-page-down"),
+    ("<C-u>", "half-page-up"),
+    ("h", "scroll-left"),
+    ("<Left>", "scroll-left"),
+    ("l", "scroll-right"),
+    ("<Right>", "scroll-right"),
+    ("gg", "top"),
+    ("G", "bottom"),
+    ("}", "next-paragraph"),
+    ("{", "previous-paragraph"),
+    ("]]", "next-heading"),
+    ("[[", "previous-heading"),
+    ("H", "screen-top"),
+    ("M", "screen-middle"),
+    ("L", "screen-bottom"),
+    ("<Tab>", "next-link"),
+    ("<S-Tab>", "previous-link"),
+    ("<S-Up>", "link-up"),
+    ("<S-Down>", "link-down"),
+    ("<S-Left>", "link-left"),
+    ("<S-Right>", "link-right"),
+    ("<Enter>", "follow-link"),
+    ("t", "follow-link-in-new-tab"),
+    ("f", "hint-follow"),
+    ("F", "hint-follow-in-new-tab"),
+    ("#", "toggle-link-numbers"),
+    ("yf", "hint-copy-url"),
+    ("<C-o>", "back"),
+    ("<BS>", "back"),
+    ("<A-Left>", "back"),
+    ("<A-Right>", "forward"),
+    ("r", "reload"),
+    ("s", "save-page"),
+    ("o", "open"),
+    ("go", "edit-url"),
+    ("O", "open-in-new-tab"),
+    ("gt", "next-tab"),
+    ("gT", "previous-tab"),
+    ("<A-1>", "tab-1"),
+    ("<A-2>", "tab-2"),
+    ("<A-3>", "tab-3"),
+    ("<A-4>", "tab-4"),
+    ("<A-5>", "tab-5"),
+    ("<A-6>", "tab-6"),
+    ("<A-7>", "tab-7"),
+    ("<A-8>", "tab-8"),
+    ("<A-9>", "tab-9"),
+    ("x", "close-tab"),
+    ("/", "search-forward"),
+    ("<C-r>", "search-backward"),
+    ("n", "next-match"),
+    ("N", "previous-match"),
+    ("<Esc>", "cancel"),
+    ("b", "bookmark"),
+    ("B", "bookmarks"),
+    ("e", "edit-bookmark"),
+    ("dd", "delete-bookmark"),
+    ("gh", "history"),
+    ("gd", "downloads"),
+    ("=", "filter"),
+    ("?", "help"),
+    ("<F1>", "help"),
+    ("q", "quit"),
+];
+
+const EMACS_PRESET: &[(&str, &str)] = &[
+    ("<C-n>", "scroll-down"),
+    ("<Down>", "scroll-down"),
+    ("<C-p>", "scroll-up"),
+    ("<Up>", "scroll-up"),
+    ("<C-v>", "page-down"),
+    ("<PageDown>", "page-down"),
+    ("<A-v>", "page-up"),
+    ("<PageUp>", "page-up"),
+    ("<C-b>", "scroll-left"),
+    ("<Left>", "scroll-left"),
+    ("<C-f>", "scroll-right"),
+    ("<Right>", "scroll-right"),
+    ("<A-lt>", "top"),
+    ("<A-gt>", "bottom"),
+    ("<A-}>", "next-paragraph"),
+    ("<A-{>", "previous-paragraph"),
+    ("<C-c><C-n>", "next-heading"),
+    ("<C-c><C-p>", "previous-heading"),
+    ("<Tab>", "next-link"),
+    ("<S-Tab>", "previous-link"),
+    ("<S-Up>", "link-up"),
+    ("<S-Down>", "link-down"),
+    ("<S-Left>", "link-left"),
+    ("<S-Right>", "link-right"),
+    ("<Enter>", "follow-link"),
+    ("<A-Enter>", "follow-link-in-new-tab"),
+    ("f", "hint-follow"),
+    ("F", "hint-follow-in-new-tab"),
+    ("#", "toggle-link-numbers"),
+    ("<A-w>", "hint-copy-url"),
+    ("l", "back"),
+    ("r", "forward"),
+    ("g", "reload"),
+    ("<C-x><C-s>", "save-page"),
+    ("G", "open"),
+    ("<C-x><C-f>", "open"),
+    ("<C-x><C-v>", "edit-url"),
+    ("<C-x>t<C-f>", "open-in-new-tab"),
+    ("<C-x>to", "next-tab"),
+    ("<C-x>tO", "previous-tab"),
+    ("<A-1>", "tab-1"),
+    ("<A-2>", "tab-2"),
+    ("<A-3>", "tab-3"),
+    ("<A-4>", "tab-4"),
+    ("<A-5>", "tab-5"),
+    ("<A-6>", "tab-6"),
+    ("<A-7>", "tab-7"),
+    ("<A-8>", "tab-8"),
+    ("<A-9>", "tab-9"),
+    ("<C-x>t0", "close-tab"),
+    ("<C-s>", "search-forward"),
+    ("<C-r>", "search-backward"),
+    ("<A-n>", "next-match"),
+    ("<A-p>", "previous-match"),
+    ("<C-g>", "cancel"),
+    ("<Esc>", "cancel"),
+    ("b", "bookmark"),
+    ("B", "bookmarks"),
+    ("e", "edit-bookmark"),
+    ("d", "delete-bookmark"),
+    ("H", "history"),
+    ("D", "downloads"),
+    ("=", "filter"),
+    ("?", "help"),
+    ("<F1>", "help"),
+    ("q", "quit"),
+    ("<C-x><C-c>", "quit"),
+];
+
+pub const PRESETS: &[(&str, &[(&str, &str)])] =
+    &[("default", DEFAULT_PRESET), ("vi", VI_PRESET), ("emacs", EMACS_PRESET)];
+
+// Outcome of the keys typed so far
+pub enum Lookup {
+    Action(Action),
+    // The start of a longer binding; wait for more keys
+    Pending,
+    Unbound,
+}
+
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset("default").unwrap()
+    }
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let (_, bindings) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+        let bindings = bindings
+            .iter()
+            .map(|(keys, action)| (parse_keys(keys).unwrap(), Action::from_name(action).unwrap()))
+            .collect();
+        Some(Keymap { bindings })
+    }
+
+    // Bind `keys` to `action`, or unbind them with None. A binding can't be
+    // the start of another one, since it would always fire first.
+    pub fn bind(&mut self, keys: &str, action: Option<Action>) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        self.bindings.retain(|(bound, _)| *bound != keys);
+        let Some(action) = action else { return Ok(()) };
+
+        if let Some((other, _)) = self.bindings.iter().find(|(bound, _)| bound.starts_with(&keys) || keys.starts_with(bound)) {
+            return Err(format!(
+                "\"{}\" overlaps \"{}\"; unbind that first with \"{}\" = \"none\"",
+                format_keys(&keys),
+                format_keys(other),
+                format_keys(other)
+            ));
+        }
+        self.bindings.push((keys, action));
+        Ok(())
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut pending = false;
+        for (bound, action) in &self.bindings {
+            if bound.as_slice() == keys {
+                return Lookup::Action(*action);
+            }
+            pending |= bound.starts_with(keys);
+        }
+        if pending {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    // All key sequences bound to `action`, e.g. "<BS>, h"
+    pub fn keys_for(&self, action: Action) -> String {
+        let keys: Vec<String> =
+            self.bindings.iter().filter(|(_, a)| *a == action).map(|(keys, _)| format_keys(keys)).collect();
+        if keys.is_empty() {
+            "unbound".to_string()
+        } else {
+            keys.join(", ")
+        }
+    }
+
+    // The about:keys page
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<html><head><title>Key bindings</title></head><body><h1>Key bindings</h1>\
+             <table><tr><th>Keys</th><th>Action</th><th>Description</th></tr>",
+        );
+        for (action, name, description) in ACTIONS {
+            if !self.bindings.iter().any(|(_, a)| a == action) {
+                continue;
+            }
+            html.push_str(&format!(
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td></tr>",
+                escape_html(&self.keys_for(*action)),
+                name,
+                description
+            ));
+        }
+        html.push_str(
+            "</table><p>Most actions take a count typed before the keys, as in \
+             <code>5j</code>.</p><p>Change them under <code>[keys]</code> in the config file, with \
+             <code>preset</code> set to default, vi or emacs and <code>[keys.bindings]</code> \
+             mapping keys to actions, or to <code>\"none\"</code> to unbind them.</p></body></html>",
+        );
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> String {
+        format_keys(&parse_keys(text).unwrap())
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        for text in [
+            "j",
+            "gg",
+            "G",
+            "<C-d>",
+            "<C-x><C-s>",
+            "<C-x>k",
+            "<A-1>",
+            "<A-Down>",
+            "<S-Tab>",
+            "<S-Up>",
+            "<Enter>",
+            "<Esc>",
+            "<BS>",
+            "<Space>",
+            "<lt>",
+            "<A-gt>",
+            "<F1>",
+            "<F12>",
+            "]]",
+            "é",
+        ] {
+            assert_eq!(round_trip(text), text);
+        }
+    }
+
+    #[test]
+    fn keys_are_normalised() {
+        assert_eq!(round_trip("<C-X>"), "<C-x>");
+        assert_eq!(round_trip("<c-d>"), "<C-d>");
+        assert_eq!(round_trip("<M-a>"), "<A-a>");
+        assert_eq!(round_trip("<CR>"), "<Enter>");
+        assert_eq!(round_trip("<Backspace>"), "<BS>");
+        assert_eq!(round_trip("<BackTab>"), "<S-Tab>");
+        assert_eq!(round_trip("<enter>"), "<Enter>");
+        assert_eq!(round_trip("<S-a>"), "a");
+        assert_eq!(round_trip("<x>"), "x");
+        assert_eq!(round_trip("< >"), "<Space>");
+        assert_eq!(round_trip("<>"), "<lt>>");
+        assert_eq!(parse_keys("<Space>").unwrap(), parse_keys(" ").unwrap());
+    }
+
+    #[test]
+    fn bad_keys() {
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("<Nope>").is_err());
+        assert!(parse_keys("<X-a>").is_err());
+        assert!(parse_keys("<F13>").is_err());
+        assert!(parse_keys("g<C-Nope>").is_err());
+    }
+
+    #[test]
+    fn presets_have_no_overlapping_bindings() {
+        for (name, bindings) in PRESETS {
+            let keymap = Keymap::preset(name).unwrap();
+            assert_eq!(keymap.bindings.len(), bindings.len());
+            for (i, (a, _)) in keymap.bindings.iter().enumerate() {
+                for (b, _) in &keymap.bindings[i + 1..] {
+                    assert!(!a.starts_with(b) && !b.starts_with(a), "{}: {} and {}", name, format_keys(a), format_keys(b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binding_and_lookup() {
+        let mut keymap = Keymap::default();
+        let keys = |text: &str| parse_keys(text).unwrap();
+        assert!(matches!(keymap.lookup(&keys("G")), Lookup::Action(Action::Bottom)));
+        assert!(matches!(keymap.lookup(&keys("g")), Lookup::Pending));
+        assert!(matches!(keymap.lookup(&keys("gg")), Lookup::Action(Action::Top)));
+        assert!(matches!(keymap.lookup(&keys("gz")), Lookup::Unbound));
+
+        assert!(keymap.bind("g", Some(Action::Reload)).is_err());
+        keymap.bind("gg", None).unwrap();
+        keymap.bind("go", None).unwrap();
+        keymap.bind("gh", None).unwrap();
+        keymap.bind("g", Some(Action::Reload)).unwrap();
+        assert!(matches!(keymap.lookup(&keys("g")), Lookup::Action(Action::Reload)));
+        assert_eq!(keymap.keys_for(Action::Reload), "r, g");
+        assert_eq!(keymap.keys_for(Action::EditUrl), "unbound");
+    }
+}
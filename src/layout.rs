@@ -0,0 +1,297 @@
+// Inline layout: flows the runs of each paragraph into lines of a given width.
+//
+// Every segment of the output remembers which link it came from, so a link
+// that wraps across lines is still highlighted and selected as one unit.
+
+use tui::style::Style;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::document::{Block, Document, Run};
+use crate::table::layout_table;
+
+#[derive(Clone)]
+pub struct Segment {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+pub struct Line {
+    pub segments: Vec<Segment>,
+    // Part of a heading, for jumping between headings
+    pub heading: bool,
+}
+
+impl Line {
+    pub fn width(&self) -> usize {
+        self.segments.iter().map(|s| s.text.width()).sum()
+    }
+
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    // Append text, merging with the previous segment when nothing differs
+    pub fn push(&mut self, text: &str, style: Style, link: Option<usize>) {
+        match self.segments.last_mut() {
+            Some(last) if last.style == style && last.link == link => last.text.push_str(text),
+            _ => self.segments.push(Segment { text: text.to_string(), style, link }),
+        }
+    }
+}
+
+// A run of non-space text that must not be broken, possibly spanning several styles
+struct Word {
+    // Style and link of the whitespace preceding the word, if any
+    space_before: Option<(Style, Option<usize>)>,
+    segments: Vec<Segment>,
+    width: usize,
+}
+
+fn split_words(runs: &[Run], number_links: bool) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current = Word { space_before: None, segments: Vec::new(), width: 0 };
+    let mut previous_link = None;
+
+    for run in runs {
+        // Lynx-style `[n]` marker in front of every link
+        if let Some(i) = run.link.filter(|_| number_links && run.link != previous_link) {
+            let marker = format!("[{}]", i + 1);
+            current.width += marker.width();
+            current.segments.push(Segment { text: marker, style: Style::default(), link: None });
+        }
+        previous_link = run.link;
+
+        for c in run.text.chars() {
+            if c == ' ' {
+                if !current.segments.is_empty() {
+                    let space_before = Some((run.style, run.link));
+                    words.push(std::mem::replace(
+                        &mut current,
+                        Word { space_before, segments: Vec::new(), width: 0 },
+                    ));
+                } else if current.space_before.is_none() && !words.is_empty() {
+                    current.space_before = Some((run.style, run.link));
+                }
+                continue;
+            }
+
+            current.width += c.width().unwrap_or(0);
+            match current.segments.last_mut() {
+                Some(last) if last.style == run.style && last.link == run.link => last.text.push(c),
+                _ => current.segments.push(Segment { text: c.to_string(), style: run.style, link: run.link }),
+            }
+        }
+    }
+    if !current.segments.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+// Greedy line filling; words wider than the line are broken by character.
+// Every line starts with `indent` columns, the first one holding the marker
+// right-aligned against the text.
+fn wrap_paragraph(
+    runs: &[Run],
+    indent: usize,
+    marker: Option<&str>,
+    width: usize,
+    number_links: bool,
+    lines: &mut Vec<Line>,
+) {
+    let marker_width = marker.map_or(0, |m| m.width() + 1);
+    // Deeply nested lists on a narrow screen still leave room for the text
+    let gutter = indent.max(marker_width).min(width / 2);
+    let first = lines.len();
+
+    wrap_words(runs, width.saturating_sub(gutter), number_links, lines);
+    if lines.len() == first && marker.is_some() {
+        lines.push(Line::default());
+    }
+
+    for (i, line) in lines[first..].iter_mut().enumerate() {
+        let prefix = match marker {
+            Some(marker) if i == 0 => {
+                format!("{}{} ", " ".repeat(gutter.saturating_sub(marker_width)), marker)
+            }
+            _ => " ".repeat(gutter),
+        };
+        if !prefix.is_empty() {
+            line.segments.insert(0, Segment { text: prefix, style: Style::default(), link: None });
+        }
+    }
+}
+
+fn wrap_words(runs: &[Run], width: usize, number_links: bool, lines: &mut Vec<Line>) {
+    let width = width.max(1);
+    let mut line = Line::default();
+    let mut line_width = 0;
+
+    for word in split_words(runs, number_links) {
+        let space = usize::from(line_width > 0 && word.space_before.is_some());
+        if line_width > 0 && line_width + space + word.width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        } else if space == 1 {
+            let (style, link) = word.space_before.unwrap();
+            line.push(" ", style, link);
+            line_width += 1;
+        }
+
+        if word.width <= width - line_width {
+            for segment in word.segments {
+                line.push(&segment.text, segment.style, segment.link);
+            }
+            line_width += word.width;
+            continue;
+        }
+
+        for segment in word.segments {
+            for c in segment.text.chars() {
+                let w = c.width().unwrap_or(0);
+                if line_width > 0 && line_width + w > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push(c.encode_utf8(&mut [0; 4]), segment.style, segment.link);
+                line_width += w;
+            }
+        }
+    }
+
+    if !line.segments.is_empty() {
+        lines.push(line);
+    }
+}
+
+const TAB_STOP: usize = 8;
+
+// Copy preformatted lines as they are, expanding tabs and padding every line
+// to a common width so the shading forms a rectangle. Lines wider than the
+// viewport are left for horizontal scrolling.
+fn layout_preformatted(
+    pre_lines: &[Vec<Run>],
+    style: Style,
+    indent: usize,
+    width: usize,
+    number_links: bool,
+    lines: &mut Vec<Line>,
+) {
+    let first = lines.len();
+    let indent = indent.min(width / 2);
+    let mut block_width = width - indent;
+    let mut previous_link = None;
+
+    for runs in pre_lines {
+        let mut line = Line::default();
+        let mut column = 0;
+        for run in runs {
+            if let Some(i) = run.link.filter(|_| number_links && run.link != previous_link) {
+                let marker = format!("[{}]", i + 1);
+                column += marker.width();
+                line.push(&marker, style, None);
+            }
+            previous_link = run.link;
+
+            let mut text = String::with_capacity(run.text.len());
+            // The text since the last tab is measured as a whole, as
+            // Line::width measures it, since the widths of single characters
+            // don't always add up to the width of the string
+            let mut measured = 0;
+            for c in run.text.chars() {
+                if c == '\t' {
+                    column += text[measured..].width();
+                    let spaces = TAB_STOP - column % TAB_STOP;
+                    text.extend(std::iter::repeat_n(' ', spaces));
+                    column += spaces;
+                    measured = text.len();
+                } else {
+                    text.push(c);
+                }
+            }
+            column += text[measured..].width();
+            line.push(&text, run.style, run.link);
+        }
+        block_width = block_width.max(column);
+        lines.push(line);
+    }
+
+    for line in &mut lines[first..] {
+        let padding = block_width.saturating_sub(line.width());
+        if padding > 0 {
+            line.push(&" ".repeat(padding), style, None);
+        }
+        if indent > 0 {
+            line.segments.insert(0, Segment { text: " ".repeat(indent), style: Style::default(), link: None });
+        }
+    }
+}
+
+// Narrowest and widest useful widths of a sequence of blocks: the longest
+// unbreakable word, and everything laid out without wrapping
+pub fn content_widths(blocks: &[Block], number_links: bool) -> (usize, usize) {
+    let mut min = 0;
+    let mut max = 0;
+
+    for block in blocks {
+        let (block_min, block_max) = match block {
+            Block::Paragraph { runs, indent, marker, .. } => {
+                let gutter = (*indent).max(marker.as_ref().map_or(0, |m| m.width() + 1));
+                let words = split_words(runs, number_links);
+                let longest = words.iter().map(|w| w.width).max().unwrap_or(0);
+                let total: usize = words
+                    .iter()
+                    .enumerate()
+                    .map(|(i, w)| w.width + usize::from(i > 0 && w.space_before.is_some()))
+                    .sum();
+                (gutter + longest, gutter + total)
+            }
+            Block::Preformatted { lines: pre_lines, style, indent } => {
+                let mut lines = Vec::new();
+                layout_preformatted(pre_lines, *style, 0, 0, number_links, &mut lines);
+                let widest = lines.iter().map(Line::width).max().unwrap_or(0);
+                (indent + widest, indent + widest)
+            }
+            Block::Table(table) => crate::table::table_widths(table, number_links),
+            Block::Blank => (0, 0),
+        };
+        min = min.max(block_min);
+        max = max.max(block_max);
+    }
+
+    (min, max)
+}
+
+// Lay a document out into lines at most `width` columns wide. With
+// `number_links` every link is prefixed with its 1-based index.
+pub fn layout(document: &Document, width: usize, number_links: bool) -> Vec<Line> {
+    let mut lines = Vec::new();
+    layout_blocks(&document.blocks, width, number_links, &mut lines);
+    lines
+}
+
+pub fn layout_blocks(blocks: &[Block], width: usize, number_links: bool, lines: &mut Vec<Line>) {
+    for block in blocks {
+        match block {
+            Block::Paragraph { runs, indent, marker, heading } => {
+                let first = lines.len();
+                wrap_paragraph(runs, *indent, marker.as_deref(), width, number_links, lines);
+                for line in &mut lines[first..] {
+                    line.heading = *heading;
+                }
+            }
+            Block::Preformatted { lines: pre_lines, style, indent } => {
+                layout_preformatted(pre_lines, *style, *indent, width, number_links, lines)
+            }
+            Block::Table(table) => layout_table(table, width, number_links, lines),
+            Block::Blank => {
+                if lines.last().is_some_and(|l: &Line| !l.segments.is_empty()) {
+                    lines.push(Line::default());
+                }
+            }
+        }
+    }
+}
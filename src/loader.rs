@@ -0,0 +1,94 @@
+// Background page loading, so the UI keeps responding while a page arrives.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use crate::http::{self, HttpClient, Request, Response};
+
+enum LoadEvent {
+    Progress { bytes: u64, total: Option<u64> },
+    Done(Result<Response, String>),
+}
+
+pub struct Load {
+    pub url: String,
+    started: Instant,
+    bytes: u64,
+    total: Option<u64>,
+    events: Receiver<LoadEvent>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Load {
+    pub fn start(request: Request, client: HttpClient) -> Self {
+        let (sender, events) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let url = request.url.clone();
+        let thread_cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let result = http::fetch(&client, &request, &thread_cancel, |bytes, total| {
+                let _ = progress_sender.send(LoadEvent::Progress { bytes, total });
+            });
+            // Nobody is listening any more if the load was cancelled
+            let _ = sender.send(LoadEvent::Done(result.map_err(|e| e.to_string())));
+        });
+
+        Load { url, started: Instant::now(), bytes: 0, total: None, events, cancel }
+    }
+
+    // A load that has already finished, for pages generated locally
+    pub fn ready(url: String, html: String) -> Self {
+        let (sender, events) = mpsc::channel();
+        let _ = sender.send(LoadEvent::Done(Ok(Response::html(url.clone(), html))));
+        let cancel = Arc::new(AtomicBool::new(false));
+        Load { url, started: Instant::now(), bytes: 0, total: None, events, cancel }
+    }
+
+    // Take in progress updates; returns the outcome once the load has finished
+    pub fn poll(&mut self) -> Option<Result<Response, String>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(LoadEvent::Progress { bytes, total }) => {
+                    self.bytes = bytes;
+                    self.total = total;
+                }
+                Ok(LoadEvent::Done(result)) => return Some(result),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Err("loader stopped unexpectedly".to_string())),
+            }
+        }
+    }
+
+    // Abandon the load; the worker thread stops at its next chunk
+    pub fn cancel(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // e.g. "Loading 12.3 KB of 40.0 KB, 1.2s"
+    pub fn status(&self) -> String {
+        let received = match self.total {
+            Some(total) => format!("{} of {}", format_bytes(self.bytes), format_bytes(total)),
+            None => format_bytes(self.bytes),
+        };
+        format!("Loading {}, {:.1}s", received, self.started.elapsed().as_secs_f64())
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
@@ -1,91 +1,139 @@
 use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use tui::{
     backend::CrosstermBackend,
     Terminal,
     widgets::{Block, Borders, Paragraph},
     layout::{Layout, Constraint, Direction},
-    style::{Style, Color, Modifier},
     text::{Text, Span, Spans},
 };
 use crossterm::{
     terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     execute,
-    event::{read, Event, KeyCode},
+    event::{poll, read, Event, KeyCode, KeyModifiers},
 };
-use scraper::{Html, Selector, ElementRef};
-use reqwest::blocking::get;
+
+mod bookmarks;
+mod cli;
+mod clipboard;
+mod config;
+mod content;
+mod cookies;
+mod document;
+mod downloads;
+mod dump;
+mod form;
+mod hints;
+mod history;
+mod http;
+mod input;
+mod keys;
+mod layout;
+mod loader;
+mod motion;
+mod paths;
+mod search;
+mod session;
+mod tab;
+mod table;
+mod util;
+mod visits;
+
+use bookmarks::Bookmarks;
+use cli::Command;
+use config::Config;
+use cookies::CookieJar;
+use downloads::Downloads;
+use form::FieldKind;
+use hints::{HintAction, HintResult, Hints};
+use http::HttpClient;
+use input::{EditResult, LineEditor};
+use keys::{Action, Key, Lookup};
+use layout::Line;
+use motion::{LinkDirection, ScreenPosition};
+use search::Search;
+use session::Session;
+use tab::{Navigation, Tab};
 use url::Url;
+use visits::Visits;
 
-struct Link {
-    url: String,
-    display_text: String,
+// What the line at the bottom of the screen is being used for
+enum Prompt {
+    // Where to go next, in this tab or a new one
+    Address { new_tab: bool },
+    // Bookmarking asks for a title, then tags
+    BookmarkTitle { url: String },
+    BookmarkTags { url: String, title: String },
+    // Narrows an about: list as you type; Esc goes back to `origin`
+    Filter { page_url: fn(&str) -> String, origin: String },
+    // Incremental search; `origin` is where to scroll back to if cancelled
+    Search { origin: u16 },
+    // Editing a form field of the page
+    Field { field: usize },
+    // Where to save the page at `url`
+    SavePage { url: String },
+    // Where to download a file that was followed
+    Download { url: String },
 }
 
-// Recursive parse function using ElementRef
-fn parse_element(
-    element: &ElementRef,
-    links: &mut Vec<Link>,
-    spans_vec: &mut Vec<(Spans<'static>, Option<usize>)>,
-    base_url: &Url,
-) {
-    let tag = element.value().name();
-
-    if tag == "a" {
-        if let Some(href) = element.value().attr("href") {
-            let url = if href.starts_with("http") {
-                href.to_string()
-            } else {
-                base_url.join(href).map(|u| u.to_string()).unwrap_or_else(|_| href.to_string())
-            };
-            let display_text = element.text().collect::<Vec<_>>().join(" ").trim().to_string();
-            if !display_text.is_empty() {
-                let link_idx = links.len();
-                links.push(Link { url, display_text: display_text.clone() });
-                spans_vec.push((Spans::from(Span::raw(display_text)), Some(link_idx)));
-            }
-        }
+// Scroll offset that brings `line` into a viewport of `height` lines,
+// leaving the offset alone if it's already visible
+fn scroll_to_reveal(line: usize, scroll_offset: u16, height: u16) -> u16 {
+    let line = line as u16;
+    if line < scroll_offset || line >= scroll_offset.saturating_add(height) {
+        line.saturating_sub(height / 3)
     } else {
-        // First recurse children nodes (both text and elements)
-        for child in element.children() {
-            if let Some(child_element) = ElementRef::wrap(child) {
-                parse_element(&child_element, links, spans_vec, base_url);
-            } else if let Some(text) = child.value().as_text() {
-                let text_str = text.text.trim();
-                if !text_str.is_empty() {
-                    spans_vec.push((Spans::from(Span::raw(text_str.to_string())), None));
-                }
-            }
-        }
-
-        // Now add a line break *only* if the current element is a block element
-        if ["p", "div", "br", "li", "ul", "ol", "section", "article"].contains(&tag) {
-            spans_vec.push((Spans::from(""), None));
-        }
+        scroll_offset
     }
 }
 
-
-fn parse_html(html: &str, base_url: &Url) -> (Vec<Link>, Vec<(Spans<'static>, Option<usize>)>) {
-    let document = Html::parse_document(html);
-    let body_selector = Selector::parse("body").unwrap();
-
-    let mut links = Vec::new();
-    let mut spans_vec = Vec::new();
-
-    if let Some(body) = document.select(&body_selector).next() {
-        parse_element(&body, &mut links, &mut spans_vec, base_url);
+// Following a form field: checkboxes and radio buttons toggle, buttons
+// submit or reset their form, and the rest open a prompt to edit them
+fn activate_field(tab: &mut Tab, field: usize, session: &Session) -> Result<Option<(Prompt, LineEditor)>, String> {
+    let f = &tab.document.fields[field];
+    if f.disabled {
+        return Err("That field is disabled".to_string());
     }
-
-    (links, spans_vec)
+    let prompt = format!("{}: ", if f.label.is_empty() { "Text" } else { &f.label });
+    let editor = match f.kind {
+        FieldKind::Checkbox | FieldKind::Radio => {
+            tab.change_fields(|document| form::toggle(document, field));
+            return Ok(None);
+        }
+        FieldKind::Submit => {
+            tab.submit_form(field, session)?;
+            return Ok(None);
+        }
+        FieldKind::Reset => {
+            if let Some(index) = f.form {
+                tab.change_fields(|document| form::reset(document, index));
+            }
+            return Ok(None);
+        }
+        FieldKind::Button | FieldKind::Hidden => return Ok(None),
+        // Up and Down step through the options, Tab completes them
+        FieldKind::Select => {
+            let labels: Vec<String> = f.options.iter().map(|(label, _)| label.clone()).collect();
+            let current = labels.get(f.selected).cloned().unwrap_or_default();
+            LineEditor::new(&prompt, &current, labels)
+        }
+        FieldKind::File => LineEditor::new("File to upload: ", &f.value, Vec::new()),
+        FieldKind::Password => LineEditor::new(&prompt, &f.value, Vec::new()).masked(),
+        FieldKind::Text => LineEditor::new(&prompt, &f.value, Vec::new()),
+        FieldKind::TextArea => {
+            let prompt = format!("{}(Alt-Enter for a new line): ", prompt);
+            LineEditor::new(&prompt, &f.value, Vec::new()).multiline()
+        }
+    };
+    Ok(Some((Prompt::Field { field }, editor)))
 }
 
-fn fetch_url(url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let response = get(url)?;
-    let body = response.text()?;
-    Ok(body)
-}
+fn display_loop(url: String, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    // Before the screen is taken over, so a bad proxy is reported plainly
+    let client = HttpClient::new(&config.fetch_options()).map_err(|e| e.to_string())?;
 
-fn display_loop(mut url: String) -> Result<(), Box<dyn std::error::Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
@@ -93,139 +141,839 @@ fn display_loop(mut url: String) -> Result<(), Box<dyn std::error::Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let link_style = Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED);
-    let selected_style = Style::default()
-        .bg(Color::Blue)
-        .fg(Color::White)
-        .add_modifier(Modifier::BOLD);
+    // Shown on the bottom line until the next key press
+    let mut message: Option<String> = None;
+
+    let bookmarks = Bookmarks::load(Bookmarks::default_path()).unwrap_or_else(|e| {
+        message = Some(format!("Could not load bookmarks: {}", e));
+        Bookmarks::default()
+    });
+    let visits = Visits::load(Visits::default_path()).unwrap_or_else(|e| {
+        message = Some(format!("Could not load history: {}", e));
+        Visits::default()
+    });
+    // A jar that can't be read is left alone on disk and not saved over
+    let cookies = CookieJar::load(CookieJar::default_path(), config.cookies.clone()).unwrap_or_else(|e| {
+        message = Some(format!("Could not load cookies: {}", e));
+        CookieJar::load(None, config.cookies.clone()).unwrap_or_default()
+    });
+    let cookies = Arc::new(cookies);
+    let client = client.with_cookies(Arc::clone(&cookies));
+    let downloads = Downloads::default();
+    let mut session = Session { config, bookmarks, visits, cookies, client, downloads };
+    let theme = session.config.theme.clone();
 
-    let mut selected_link_idx: Option<usize> = None;
-    let mut scroll_offset: u16 = 0;
+    let mut tabs = vec![Tab::new(url, &session)];
+    let mut active = 0;
 
-    // Cache for current page
-    let mut current_links: Vec<Link> = Vec::new();
-    let mut current_spans: Vec<(Spans<'static>, Option<usize>)> = Vec::new();
-    let mut last_url = String::new();
+    // Pages visited, most recent last; feeds the address bar
+    let mut visited: Vec<String> = session.visits.recent_urls();
+    let mut prompt: Option<(Prompt, LineEditor)> = None;
+
+    // Settings and queries remembered across searches
+    let mut search_history: Vec<String> = Vec::new();
+    let mut search_regex = false;
+    let mut search_case_sensitive = false;
+    // Keys typed so far towards a multi-key binding such as `gg`
+    let mut pending_keys: Vec<Key> = Vec::new();
+    // Count typed before a binding, as in `5j`
+    let mut count: Option<usize> = None;
+    // Whether links show their `[n]` numbers
+    let mut number_links = session.config.number_links;
+    // Labels over the links on screen while picking one by hint
+    let mut hints: Option<Hints> = None;
+    // Height of the page area at the last draw
+    let mut page_height: u16 = 0;
 
     loop {
-        // Fetch and parse only if URL changed
-        if url != last_url {
-            match fetch_url(&url) {
-                Ok(html) => {
-                    let base_url = Url::parse(&url)?;
-                    let (links, spans_vec) = parse_html(&html, &base_url);
-                    current_links = links;
-                    current_spans = spans_vec;
-                    last_url = url.clone();
-                    selected_link_idx = None;
-                    scroll_offset = 0;
-                }
-                Err(e) => {
-                    // Show an error message as spans if fetch fails
-                    current_links.clear();
-                    current_spans = vec![(Spans::from(Span::raw(format!("Error fetching URL: {}", e))), None)];
-                    last_url = url.clone();
-                    selected_link_idx = None;
-                    scroll_offset = 0;
-                }
-            }
-        }
-
-        // Prepare styled lines based on current_spans & selection as before
-        let styled_lines: Vec<Spans> = current_spans
+        // Background tabs keep loading too
+        for (i, tab) in tabs.iter_mut().enumerate() {
+            let was_loading = tab.loading.is_some();
+            let loaded = tab.poll_loading(&session);
+            // Hints and field edits point into the page they were made for
+            if i == active && was_loading && tab.loading.is_none() {
+                hints = None;
+                if matches!(prompt, Some((Prompt::Field { .. }, _))) {
+                    prompt = None;
+                }
+            }
+            let Some(loaded) = loaded else { continue };
+            if loaded.starts_with("about:") {
+                continue;
+            }
+            if let Err(e) = session.visits.record(&loaded, tab.document.title.as_deref()) {
+                message = Some(format!("Could not save history: {}", e));
+            }
+            visited.retain(|v| *v != loaded);
+            visited.push(loaded);
+        }
+
+        // A followed file asks where to go once nothing else is being typed
+        if prompt.is_none() && hints.is_none() {
+            if let Some(offer) = tabs.iter_mut().find_map(|t| t.download_offer.take()) {
+                let size = offer.size.map_or(String::new(), |size| format!(" ({})", loader::format_bytes(size)));
+                let path = paths::download_dir().map_or_else(|| offer.file_name.clone().into(), |dir| dir.join(&offer.file_name));
+                let editor = LineEditor::new(&format!("Download{} to: ", size), &path.display().to_string(), Vec::new());
+                prompt = Some((Prompt::Download { url: offer.url }, editor));
+            }
+        }
+        let ended = session.downloads.take_ended();
+        if !ended.is_empty() {
+            message = Some(ended.join("; "));
+        }
+        let download_status = session.downloads.status();
+
+        // The tab bar only appears once there is more than one tab; two
+        // columns go to the page border
+        let show_tab_bar = tabs.len() > 1;
+        let size = terminal.size()?;
+        let tab = &mut tabs[active];
+        tab.relayout(size.width.saturating_sub(2) as usize, number_links);
+
+        // Prepare styled lines, highlighting every segment of the selected link
+        // and any search matches. Hint labels cover the start of each link on
+        // screen instead of search matches.
+        let mut labelled: Vec<usize> = Vec::new();
+        let styled_lines: Vec<Spans> = tab.lines
             .iter()
-            .map(|(spans, link_idx_opt)| {
-                if let Some(link_idx) = link_idx_opt {
-                    let style = if Some(*link_idx) == selected_link_idx {
-                        selected_style
-                    } else {
-                        link_style
+            .enumerate()
+            .map(|(i, line)| {
+                let mut spans = Vec::with_capacity(line.segments.len());
+                for segment in &line.segments {
+                    let style = match segment.link {
+                        Some(i) if Some(i) == tab.selected_link_idx => segment.style.patch(theme.selected_link),
+                        Some(_) => segment.style.patch(theme.link),
+                        None => segment.style,
                     };
-                    let styled_spans = spans.0.iter()
-                        .map(|span| Span::styled(span.content.clone(), style))
-                        .collect::<Vec<_>>();
-                    Spans::from(styled_spans)
-                } else {
-                    spans.clone()
+                    let label = match (&hints, segment.link) {
+                        (Some(hints), Some(link)) if i >= tab.scroll_offset as usize && !labelled.contains(&link) => {
+                            labelled.push(link);
+                            hints.label(link)
+                        }
+                        _ => None,
+                    };
+                    match label {
+                        Some(label) => {
+                            let rest: String = segment.text.chars().skip(label.chars().count()).collect();
+                            spans.push(Span::styled(label.to_string(), theme.hint));
+                            spans.push(Span::styled(rest, style));
+                        }
+                        None => spans.push(Span::styled(segment.text.clone(), style)),
+                    }
+                }
+                match (&tab.search, &hints) {
+                    (Some(search), None) => Spans::from(search.highlight(i, spans, &theme)),
+                    _ => Spans::from(spans),
                 }
             })
             .collect();
 
+        // The old page stays up while the new one loads, with progress in the title
+        let title = match (&tab.loading, &tab.search) {
+            (Some((_, load)), _) => format!("{}  [{}: {}]", tab.url, load.url, load.status()),
+            (None, Some(search)) => format!("{}  [{}]", tab.url, search.status()),
+            (None, None) => tab.url.clone(),
+        };
+
+        let tab_bar: Vec<Span> = if show_tab_bar {
+            tabs.iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    let label: String = t.label().chars().take(24).collect();
+                    let text = format!(" {}: {} ", i + 1, label);
+                    if i == active {
+                        Span::styled(text, theme.active_tab)
+                    } else {
+                        Span::raw(text)
+                    }
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let tab = &mut tabs[active];
         terminal.draw(|f| {
             let size = f.size();
+            let mut constraints = vec![Constraint::Min(1)];
+            if show_tab_bar {
+                constraints.insert(0, Constraint::Length(1));
+            }
+            if prompt.is_some() || message.is_some() || download_status.is_some() {
+                constraints.push(Constraint::Length(1));
+            }
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([Constraint::Min(1)].as_ref())
+                .constraints(constraints)
                 .split(size);
+            let (page_area, prompt_area) = if show_tab_bar {
+                f.render_widget(Paragraph::new(Spans::from(tab_bar)), chunks[0]);
+                (chunks[1], chunks.get(2).copied())
+            } else {
+                (chunks[0], chunks.get(1).copied())
+            };
 
-            let max_scroll = styled_lines.len().saturating_sub(chunks[0].height as usize) as u16;
-            if scroll_offset > max_scroll {
-                scroll_offset = max_scroll;
+            page_height = page_area.height.saturating_sub(2);
+            let max_scroll = styled_lines.len().saturating_sub(page_height as usize) as u16;
+            if tab.scroll_offset > max_scroll {
+                tab.scroll_offset = max_scroll;
+            }
+            let widest = tab.lines.iter().map(Line::width).max().unwrap_or(0);
+            let max_hscroll = widest.saturating_sub(page_area.width.saturating_sub(2) as usize) as u16;
+            if tab.hscroll_offset > max_hscroll {
+                tab.hscroll_offset = max_hscroll;
             }
 
             let paragraph = Paragraph::new(Text::from(styled_lines))
-                .block(Block::default().title(url.as_str()).borders(Borders::ALL))
-                .scroll((scroll_offset, 0));
-            f.render_widget(paragraph, chunks[0]);
+                .block(Block::default().title(title.as_str()).borders(Borders::ALL))
+                .scroll((tab.scroll_offset, tab.hscroll_offset));
+            f.render_widget(paragraph, page_area);
+
+            if let (Some((_, editor)), Some(area)) = (&prompt, prompt_area) {
+                // Scroll the line horizontally so the cursor stays visible
+                let cursor = editor.cursor_column() as u16;
+                let offset = cursor.saturating_sub(area.width.saturating_sub(1));
+                let line = Paragraph::new(format!("{}{}", editor.prompt, editor.shown_text()))
+                    .scroll((0, offset));
+                f.render_widget(line, area);
+                f.set_cursor(area.x + cursor - offset, area.y);
+            } else if let (Some(message), Some(area)) = (&message, prompt_area) {
+                f.render_widget(Paragraph::new(message.as_str()), area);
+            } else if let (Some(status), Some(area)) = (&download_status, prompt_area) {
+                f.render_widget(Paragraph::new(status.as_str()), area);
+            }
         })?;
 
-        // Read input event and handle navigation, scrolling, etc.
-        if let Event::Key(key) = read()? {
-            match key.code {
-                KeyCode::Char('q') => break,
-
-                KeyCode::Tab => {
-                    if !current_links.is_empty() {
-                        selected_link_idx = Some(match selected_link_idx {
-                            None => 0,
-                            Some(i) => (i + 1) % current_links.len(),
-                        });
-                    }
+        // Wait for input; while loading, wake up regularly to update progress
+        let any_loading = tabs.iter().any(|t| t.loading.is_some()) || session.downloads.any_active();
+        let event = if !any_loading || poll(Duration::from_millis(100))? {
+            Some(read()?)
+        } else {
+            None
+        };
+
+        // Handle navigation, scrolling, etc.
+        let Some(Event::Key(key)) = event else { continue };
+        message = None;
+        let tab_count = tabs.len();
+        let tab = &mut tabs[active];
+
+        if let Some(active_hints) = hints.as_mut() {
+            let result = match key.code {
+                KeyCode::Esc => {
+                    hints = None;
+                    continue;
+                }
+                KeyCode::Backspace => {
+                    active_hints.backspace();
+                    HintResult::Continue
                 }
-                KeyCode::BackTab => {
-                    if !current_links.is_empty() {
-                        selected_link_idx = Some(match selected_link_idx {
-                            None => current_links.len() - 1,
-                            Some(i) => if i == 0 { current_links.len() - 1 } else { i - 1 },
-                        });
+                KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                    active_hints.type_char(c)
+                }
+                _ => HintResult::Continue,
+            };
+            match result {
+                HintResult::Continue => message = Some(active_hints.prompt()),
+                HintResult::NoMatch => {
+                    hints = None;
+                    message = Some("No link has that hint".to_string());
+                }
+                HintResult::Picked(link) => {
+                    let action = active_hints.action;
+                    hints = None;
+                    let Some(url) = tab.document.links.get(link).map(|l| l.url.clone()) else { continue };
+                    tab.selected_link_idx = Some(link);
+                    // Form fields are edited in place, whichever way they are followed
+                    let field = tab.document.links[link].field.filter(|_| action != HintAction::CopyUrl);
+                    if let Some(field) = field {
+                        match activate_field(tab, field, &session) {
+                            Ok(field_prompt) => prompt = field_prompt,
+                            Err(e) => message = Some(e),
+                        }
+                        continue;
+                    }
+                    match action {
+                        HintAction::Follow => tab.navigate(Navigation::Follow(url), &session),
+                        HintAction::FollowInNewTab => {
+                            tabs.insert(active + 1, Tab::new(url, &session));
+                            active += 1;
+                        }
+                        HintAction::CopyUrl => {
+                            message = Some(match clipboard::copy(&url) {
+                                Ok(()) => format!("Copied {}", url),
+                                Err(e) => format!("Could not copy: {}", e),
+                            });
+                        }
                     }
                 }
-                KeyCode::Enter => {
-                    if let Some(i) = selected_link_idx {
-                        if let Some(link) = current_links.get(i) {
-                            url = link.url.clone();
-                            // Fetch happens next loop iteration because url changed
+            }
+            continue;
+        }
+
+        if let Some((Prompt::Address { new_tab }, editor)) = prompt.as_mut() {
+            let new_tab = *new_tab;
+            match editor.handle_key(key, &visited) {
+                EditResult::Submit(text) => {
+                    prompt = None;
+                    if !text.trim().is_empty() {
+                        let target = cli::resolve_address(&text, &session.config.search_url);
+                        if new_tab {
+                            tabs.insert(active + 1, Tab::new(target, &session));
+                            active += 1;
+                        } else {
+                            tab.navigate(Navigation::Push(target), &session);
                         }
                     }
                 }
-                KeyCode::Down => {
-                    scroll_offset = scroll_offset.saturating_add(1);
+                EditResult::Cancel => prompt = None,
+                EditResult::Continue => {}
+            }
+            continue;
+        }
+
+        if let Some((Prompt::Search { origin }, editor)) = prompt.as_mut() {
+            let origin = *origin;
+            let Some(active_search) = tab.search.as_mut() else { continue };
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+            // Ctrl-R toggles regex mode, Ctrl-T case sensitivity
+            let result = match key.code {
+                KeyCode::Char('r') if ctrl => {
+                    search_regex = !search_regex;
+                    active_search.regex = search_regex;
+                    EditResult::Continue
+                }
+                KeyCode::Char('t') if ctrl => {
+                    search_case_sensitive = !search_case_sensitive;
+                    active_search.case_sensitive = search_case_sensitive;
+                    EditResult::Continue
+                }
+                _ => editor.handle_key(key, &[]),
+            };
+
+            match result {
+                EditResult::Submit(text) => {
+                    prompt = None;
+                    // An empty query repeats the previous search
+                    let text = match search_history.last() {
+                        Some(last) if text.is_empty() => last.clone(),
+                        _ => text,
+                    };
+                    if text.is_empty() {
+                        tab.search = None;
+                        continue;
+                    }
+                    search_history.retain(|q| *q != text);
+                    search_history.push(text.clone());
+                    active_search.query = text;
+                }
+                EditResult::Cancel => {
+                    prompt = None;
+                    tab.search = None;
+                    tab.scroll_offset = origin;
+                    continue;
+                }
+                EditResult::Continue => active_search.query = editor.text(),
+            }
+
+            // Search as you type, starting from where the search began
+            active_search.find_all(&tab.lines);
+            let start = if active_search.backward {
+                (origin + page_height).saturating_sub(1)
+            } else {
+                origin
+            };
+            tab.scroll_offset = match active_search.select_from(start as usize) {
+                Some(line) => scroll_to_reveal(line, origin, page_height),
+                None => origin,
+            };
+            continue;
+        }
+
+        if let Some((Prompt::Filter { page_url, origin }, editor)) = prompt.as_mut() {
+            let target = match editor.handle_key(key, &[]) {
+                EditResult::Submit(text) => {
+                    let target = page_url(&text);
+                    prompt = None;
+                    target
+                }
+                EditResult::Cancel => {
+                    let origin = origin.clone();
+                    prompt = None;
+                    origin
+                }
+                EditResult::Continue => page_url(&editor.text()),
+            };
+            if target != tab.url {
+                tab.navigate(Navigation::Replace(target), &session);
+            }
+            continue;
+        }
+
+        if let Some((Prompt::Field { field }, editor)) = prompt.as_mut() {
+            let field = *field;
+            let options: Vec<String> = tab.document.fields[field].options.iter().map(|(label, _)| label.clone()).collect();
+            match editor.handle_key(key, &options) {
+                EditResult::Submit(text) => {
+                    prompt = None;
+                    let mut result = Ok(());
+                    tab.change_fields(|document| result = form::set_value(document, field, &text));
+                    let submit = tab.document.fields[field].is_text() && form::submits_on_enter(&tab.document, field);
+                    if let Err(e) = result.and_then(|()| if submit { tab.submit_form(field, &session) } else { Ok(()) }) {
+                        message = Some(e);
+                    }
+                }
+                EditResult::Cancel => prompt = None,
+                EditResult::Continue => {}
+            }
+            continue;
+        }
+
+        if let Some((Prompt::SavePage { url }, editor)) = prompt.as_mut() {
+            match editor.handle_key(key, &[]) {
+                EditResult::Submit(text) => {
+                    let url = url.clone();
+                    prompt = None;
+                    let path = paths::expand_home(text.trim());
+                    // A load finishing while the prompt was open replaces the page
+                    message = Some(match tab.response.as_ref().filter(|r| r.url == url) {
+                        Some(response) => match content::save(&response.bytes, &path) {
+                            Ok(()) => format!("Saved {} to {}", loader::format_bytes(response.bytes.len() as u64), path.display()),
+                            Err(e) => format!("Could not save {}: {}", path.display(), e),
+                        },
+                        None => "The page changed; nothing was saved".to_string(),
+                    });
                 }
-                KeyCode::Up => {
-                    scroll_offset = scroll_offset.saturating_sub(1);
+                EditResult::Cancel => prompt = None,
+                EditResult::Continue => {}
+            }
+            continue;
+        }
+
+        if let Some((Prompt::Download { url }, editor)) = prompt.as_mut() {
+            match editor.handle_key(key, &[]) {
+                EditResult::Submit(text) => {
+                    let url = url.clone();
+                    prompt = None;
+                    let path = paths::expand_home(text.trim());
+                    message = Some(match session.downloads.start(&url, path, &session.client) {
+                        Ok(()) => format!("Downloading; {} lists downloads", downloads::PAGE_URL),
+                        Err(e) => e,
+                    });
                 }
-                KeyCode::PageDown => {
-                    scroll_offset = scroll_offset.saturating_add(10);
+                EditResult::Cancel => prompt = None,
+                EditResult::Continue => {}
+            }
+            continue;
+        }
+
+        if let Some((Prompt::BookmarkTitle { .. } | Prompt::BookmarkTags { .. }, editor)) = prompt.as_mut() {
+            let text = match editor.handle_key(key, &[]) {
+                EditResult::Submit(text) => text,
+                EditResult::Cancel => {
+                    prompt = None;
+                    continue;
+                }
+                EditResult::Continue => continue,
+            };
+            let Some((kind, _)) = prompt.take() else { continue };
+            match kind {
+                Prompt::BookmarkTitle { url } => {
+                    let tags = session.bookmarks.get(&url).map(|b| b.tags.join(", ")).unwrap_or_default();
+                    let editor = LineEditor::new("Tags (comma-separated): ", &tags, Vec::new());
+                    prompt = Some((Prompt::BookmarkTags { url, title: text }, editor));
                 }
-                KeyCode::PageUp => {
-                    scroll_offset = scroll_offset.saturating_sub(10);
+                Prompt::BookmarkTags { url, title } => {
+                    session.bookmarks.add(&url, &title, bookmarks::parse_tags(&text));
+                    message = Some(match session.bookmarks.save() {
+                        Ok(()) => format!("Bookmarked {}", url),
+                        Err(e) => format!("Could not save bookmarks: {}", e),
+                    });
+                    if tab.url.starts_with(bookmarks::PAGE_URL) {
+                        tab.navigate(Navigation::Reload, &session);
+                    }
                 }
                 _ => {}
             }
+            continue;
+        }
+
+        // The bookmark behind the selected link on the bookmarks page
+        let selected_bookmark = tab
+            .selected_link_idx
+            .and_then(|i| tab.document.links.get(i))
+            .filter(|_| tab.url.starts_with(bookmarks::PAGE_URL))
+            .and_then(|link| session.bookmarks.get(&link.url));
+
+        // Digits before a binding make a count, unless bound themselves
+        let typed = Key::from_event(&key);
+        let keymap = &session.config.keymap;
+        let digit = typed.digit().filter(|&d| pending_keys.is_empty() && (d > 0 || count.is_some()));
+        if let Some(digit) = digit.filter(|_| matches!(keymap.lookup(&[typed]), Lookup::Unbound)) {
+            let value = count.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+            count = Some(value);
+            message = Some(value.to_string());
+            continue;
+        }
+
+        // Collect keys until they make up a whole binding
+        pending_keys.push(typed);
+        let action = match keymap.lookup(&pending_keys) {
+            Lookup::Action(action) => action,
+            Lookup::Pending => {
+                let count = count.map_or(String::new(), |c| c.to_string());
+                message = Some(format!("{}{}", count, keys::format_keys(&pending_keys)));
+                continue;
+            }
+            Lookup::Unbound => {
+                pending_keys.clear();
+                count = None;
+                continue;
+            }
+        };
+        pending_keys.clear();
+        let given_count = count.take();
+        let repeat = given_count.unwrap_or(1);
+        let lines = u16::try_from(repeat).unwrap_or(u16::MAX);
+
+        let link_count = tab.document.links.len();
+        match action {
+            Action::Quit => break,
+            Action::Help => tab.navigate(Navigation::Push(keys::PAGE_URL.to_string()), &session),
+
+            // Without a selection, start from the links on screen
+            Action::NextLink if link_count > 0 => {
+                let visible = motion::visible_links(&tab.lines, tab.scroll_offset as usize, page_height as usize);
+                tab.selected_link_idx = Some(match (tab.selected_link_idx, visible.first()) {
+                    (Some(i), _) => (i + repeat) % link_count,
+                    (None, Some(&first)) => (first + repeat - 1) % link_count,
+                    (None, None) => (repeat - 1) % link_count,
+                });
+                tab.reveal_selection(page_height);
+            }
+            Action::PreviousLink if link_count > 0 => {
+                let visible = motion::visible_links(&tab.lines, tab.scroll_offset as usize, page_height as usize);
+                let back = repeat % link_count;
+                tab.selected_link_idx = Some(match (tab.selected_link_idx, visible.last()) {
+                    (Some(i), _) => (i + link_count - back) % link_count,
+                    (None, Some(&last)) => (last + 1 + link_count - back) % link_count,
+                    (None, None) => (link_count - back) % link_count,
+                });
+                tab.reveal_selection(page_height);
+            }
+            Action::LinkUp | Action::LinkDown | Action::LinkLeft | Action::LinkRight => {
+                let direction = match action {
+                    Action::LinkUp => LinkDirection::Up,
+                    Action::LinkDown => LinkDirection::Down,
+                    Action::LinkLeft => LinkDirection::Left,
+                    _ => LinkDirection::Right,
+                };
+                tab.selected_link_idx = match tab.selected_link_idx {
+                    Some(mut link) => {
+                        for _ in 0..repeat {
+                            let Some(next) = motion::link_towards(&tab.lines, link, direction) else { break };
+                            link = next;
+                        }
+                        Some(link)
+                    }
+                    None => {
+                        let top = tab.scroll_offset as usize;
+                        motion::visible_links(&tab.lines, top, page_height as usize).first().copied()
+                    }
+                };
+                tab.reveal_selection(page_height);
+            }
+            // A count picks a link by its number, as in `12<Enter>`
+            Action::FollowLink | Action::FollowLinkInNewTab => {
+                if let Some(n) = given_count {
+                    if n > link_count {
+                        message = Some(format!("No link {}", n));
+                        continue;
+                    }
+                    tab.selected_link_idx = Some(n - 1);
+                }
+                let Some(link) = tab.selected_link_idx.and_then(|i| tab.document.links.get(i)) else { continue };
+                if let Some(field) = link.field {
+                    match activate_field(tab, field, &session) {
+                        Ok(field_prompt) => prompt = field_prompt,
+                        Err(e) => message = Some(e),
+                    }
+                } else if action == Action::FollowLink {
+                    tab.navigate(Navigation::Follow(link.url.clone()), &session);
+                } else {
+                    let new_tab = Tab::new(link.url.clone(), &session);
+                    tabs.insert(active + 1, new_tab);
+                    active += 1;
+                }
+            }
+            Action::ToggleLinkNumbers => number_links = !number_links,
+            Action::HintFollow | Action::HintFollowInNewTab | Action::HintCopyUrl => {
+                let action = match action {
+                    Action::HintFollow => HintAction::Follow,
+                    Action::HintFollowInNewTab => HintAction::FollowInNewTab,
+                    _ => HintAction::CopyUrl,
+                };
+                let (top, height) = (tab.scroll_offset as usize, page_height as usize);
+                hints = Hints::new(action, &tab.lines, top, height, &session.config.hint_chars);
+                message = Some(hints.as_ref().map_or("No links on screen".to_string(), Hints::prompt));
+            }
+            Action::Back => tab.navigate(Navigation::Back, &session),
+            Action::Forward => tab.navigate(Navigation::Forward, &session),
+            Action::Reload => tab.navigate(Navigation::Reload, &session),
+
+            Action::Open => {
+                let editor = LineEditor::new("Go to: ", "", visited.clone());
+                prompt = Some((Prompt::Address { new_tab: false }, editor));
+            }
+            Action::EditUrl => {
+                let editor = LineEditor::new("Go to: ", &tab.url, visited.clone());
+                prompt = Some((Prompt::Address { new_tab: false }, editor));
+            }
+            Action::OpenInNewTab => {
+                let editor = LineEditor::new("Open in new tab: ", "", visited.clone());
+                prompt = Some((Prompt::Address { new_tab: true }, editor));
+            }
+
+            // As in Vim, a count picks the tab rather than repeating
+            Action::NextTab => match given_count {
+                Some(n) if n <= tab_count => active = n - 1,
+                Some(_) => {}
+                None => active = (active + 1) % tab_count,
+            },
+            Action::PreviousTab => active = (active + tab_count - repeat % tab_count) % tab_count,
+            Action::GoToTab(index) if index < tab_count => active = index,
+            Action::CloseTab if tab_count > 1 => {
+                tabs.remove(active).cancel_loading();
+                active = active.min(tabs.len() - 1);
+            }
+
+            Action::SavePage => match &tab.response {
+                Some(response) => {
+                    let name = content::file_name(&response.url);
+                    let path = paths::download_dir().map_or_else(|| name.clone().into(), |dir| dir.join(&name));
+                    let editor = LineEditor::new("Save to: ", &path.display().to_string(), Vec::new());
+                    prompt = Some((Prompt::SavePage { url: response.url.clone() }, editor));
+                }
+                None => message = Some("Nothing to save".to_string()),
+            },
+
+            // Bookmarking the current page adds it or edits the existing
+            // bookmark; editing and deleting work on the bookmarks page
+            Action::Bookmark => {
+                let title = match session.bookmarks.get(&tab.url) {
+                    Some(bookmark) => bookmark.title.clone(),
+                    None => tab.document.title.clone().unwrap_or_default(),
+                };
+                let editor = LineEditor::new("Bookmark title: ", &title, Vec::new());
+                prompt = Some((Prompt::BookmarkTitle { url: tab.url.clone() }, editor));
+            }
+            Action::Bookmarks => tab.navigate(Navigation::Push(bookmarks::PAGE_URL.to_string()), &session),
+            Action::EditBookmark => {
+                if let Some(bookmark) = selected_bookmark {
+                    let editor = LineEditor::new("Bookmark title: ", &bookmark.title, Vec::new());
+                    prompt = Some((Prompt::BookmarkTitle { url: bookmark.url.clone() }, editor));
+                }
+            }
+            Action::DeleteBookmark => {
+                if let Some(url) = selected_bookmark.map(|b| b.url.clone()) {
+                    session.bookmarks.remove(&url);
+                    message = Some(match session.bookmarks.save() {
+                        Ok(()) => format!("Deleted bookmark {}", url),
+                        Err(e) => format!("Could not save bookmarks: {}", e),
+                    });
+                    tab.navigate(Navigation::Reload, &session);
+                }
+            }
+            Action::History => tab.navigate(Navigation::Push(visits::PAGE_URL.to_string()), &session),
+            Action::Downloads => tab.navigate(Navigation::Push(downloads::PAGE_URL.to_string()), &session),
+
+            // Narrow the bookmarks, history and cookies pages as you type
+            Action::Filter => {
+                let page_url: Option<fn(&str) -> String> = if tab.url.starts_with(bookmarks::PAGE_URL) {
+                    Some(bookmarks::page_url)
+                } else if tab.url.starts_with(visits::PAGE_URL) {
+                    Some(visits::page_url)
+                } else if tab.url.starts_with(cookies::PAGE_URL) {
+                    Some(cookies::page_url)
+                } else {
+                    None
+                };
+                if let Some(page_url) = page_url {
+                    let query = Url::parse(&tab.url)
+                        .ok()
+                        .and_then(|u| u.query_pairs().find(|(key, _)| key == "q").map(|(_, q)| q.into_owned()))
+                        .unwrap_or_default();
+                    let editor = LineEditor::new("Filter: ", &query, Vec::new());
+                    prompt = Some((Prompt::Filter { page_url, origin: tab.url.clone() }, editor));
+                }
+            }
+
+            Action::SearchForward | Action::SearchBackward => {
+                let backward = action == Action::SearchBackward;
+                let editor = LineEditor::new(if backward { "?" } else { "/" }, "", search_history.clone());
+                prompt = Some((Prompt::Search { origin: tab.scroll_offset }, editor));
+                tab.search = Some(Search::new(backward, search_regex, search_case_sensitive));
+            }
+            Action::NextMatch | Action::PreviousMatch => {
+                let reverse = action == Action::PreviousMatch;
+                let Some(search) = tab.search.as_mut() else { continue };
+                if let Some(line) = (0..repeat).filter_map(|_| search.step(reverse)).last() {
+                    tab.scroll_offset = scroll_to_reveal(line, tab.scroll_offset, page_height);
+                }
+            }
+            // Stop a page load first, then clear search highlights
+            Action::Cancel if !tab.cancel_loading() => tab.search = None,
+
+            Action::ScrollDown => {
+                tab.scroll_offset = tab.scroll_offset.saturating_add(session.config.scroll_line.saturating_mul(lines));
+            }
+            Action::ScrollUp => {
+                tab.scroll_offset = tab.scroll_offset.saturating_sub(session.config.scroll_line.saturating_mul(lines));
+            }
+            Action::PageDown => {
+                tab.scroll_offset = tab.scroll_offset.saturating_add(session.config.scroll_page.saturating_mul(lines));
+            }
+            Action::PageUp => {
+                tab.scroll_offset = tab.scroll_offset.saturating_sub(session.config.scroll_page.saturating_mul(lines));
+            }
+            Action::HalfPageDown => {
+                let half = (page_height / 2).max(1);
+                tab.scroll_offset = tab.scroll_offset.saturating_add(half.saturating_mul(lines));
+            }
+            Action::HalfPageUp => {
+                let half = (page_height / 2).max(1);
+                tab.scroll_offset = tab.scroll_offset.saturating_sub(half.saturating_mul(lines));
+            }
+            Action::ScrollRight => {
+                let columns = session.config.scroll_horizontal.saturating_mul(lines);
+                tab.hscroll_offset = tab.hscroll_offset.saturating_add(columns);
+            }
+            Action::ScrollLeft => {
+                let columns = session.config.scroll_horizontal.saturating_mul(lines);
+                tab.hscroll_offset = tab.hscroll_offset.saturating_sub(columns);
+            }
+            // With a count both go to that line, as in Vim. The offset is
+            // clamped to the end of the page afterwards.
+            Action::Top | Action::Bottom => {
+                tab.scroll_offset = match given_count {
+                    Some(line) => u16::try_from(line - 1).unwrap_or(u16::MAX),
+                    None if action == Action::Top => 0,
+                    None => u16::MAX,
+                };
+            }
+            Action::NextParagraph | Action::PreviousParagraph | Action::NextHeading | Action::PreviousHeading => {
+                let starts = match action {
+                    Action::NextParagraph | Action::PreviousParagraph => motion::paragraph_starts(&tab.lines),
+                    _ => motion::heading_starts(&tab.lines),
+                };
+                let backward = matches!(action, Action::PreviousParagraph | Action::PreviousHeading);
+                if let Some(line) = motion::jump(&starts, tab.scroll_offset as usize, repeat, backward) {
+                    tab.scroll_offset = u16::try_from(line).unwrap_or(u16::MAX);
+                }
+            }
+            Action::ScreenTop | Action::ScreenMiddle | Action::ScreenBottom => {
+                let position = match action {
+                    Action::ScreenTop => ScreenPosition::Top,
+                    Action::ScreenMiddle => ScreenPosition::Middle,
+                    _ => ScreenPosition::Bottom,
+                };
+                let (top, height) = (tab.scroll_offset as usize, page_height as usize);
+                if let Some(link) = motion::screen_link(&tab.lines, top, height, position, repeat) {
+                    tab.selected_link_idx = Some(link);
+                }
+            }
+            _ => {}
+        }
+
+        // Scrolling drags the selection along so it stays on screen
+        let scrolled = matches!(
+            action,
+            Action::ScrollDown
+                | Action::ScrollUp
+                | Action::PageDown
+                | Action::PageUp
+                | Action::HalfPageDown
+                | Action::HalfPageUp
+                | Action::Top
+                | Action::Bottom
+                | Action::NextParagraph
+                | Action::PreviousParagraph
+                | Action::NextHeading
+                | Action::PreviousHeading
+                | Action::NextMatch
+                | Action::PreviousMatch
+        );
+        if scrolled {
+            tabs[active].keep_selection_visible(page_height);
         }
     }
 
     disable_raw_mode()?;
     execute!(io::stdout(), LeaveAlternateScreen)?;
 
+    session.cookies.save()?;
     Ok(())
 }
 
+fn import_bookmarks(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bookmarks = Bookmarks::load(Bookmarks::default_path())?;
+    let imported = bookmarks::import_file(&mut bookmarks, path)?;
+    println!("Imported {} bookmarks", imported);
+    Ok(())
+}
 
+fn export_bookmarks(path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let bookmarks = Bookmarks::load(Bookmarks::default_path())?;
+    let html = bookmarks.export_netscape();
+    match path {
+        Some(path) => std::fs::write(path, html)?,
+        None => print!("{}", html),
+    }
+    Ok(())
+}
 
 fn main() {
-    let start_url = "https://en.wikipedia.org/wiki/Main_Page".to_string();
-    if let Err(e) = display_loop(start_url) {
+    let command = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), e);
+            eprintln!("Try '{} --help' for more information.", env!("CARGO_PKG_NAME"));
+            std::process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Help => {
+            println!("{}", cli::usage());
+            Ok(())
+        }
+        Command::Version => {
+            println!("{}", cli::version());
+            Ok(())
+        }
+        Command::ImportBookmarks(path) => import_bookmarks(&path),
+        Command::ExportBookmarks(path) => export_bookmarks(path.as_deref()),
+        Command::Run(options) => match config::load(options.config.as_deref()) {
+            Ok(mut config) => {
+                if let Some(user_agent) = options.user_agent {
+                    config.user_agent = user_agent;
+                }
+                let url = options.url.unwrap_or_else(|| config.homepage.clone());
+                if options.dump {
+                    dump::dump_page(&url, options.width, &config)
+                } else {
+                    display_loop(url, config)
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), e);
+                std::process::exit(2);
+            }
+        },
+    };
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
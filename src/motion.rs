@@ -0,0 +1,148 @@
+// Vim-style motions over the rendered lines of a page. There is no text
+// cursor: jumps move the view so the target line is at the top, and the
+// screen motions (H, M, L) move the link selection instead.
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::layout::Line;
+
+// Lines where a paragraph begins: non-empty lines after an empty one
+pub fn paragraph_starts(lines: &[Line]) -> Vec<usize> {
+    (0..lines.len())
+        .filter(|&i| !lines[i].segments.is_empty() && (i == 0 || lines[i - 1].segments.is_empty()))
+        .collect()
+}
+
+// First lines of headings
+pub fn heading_starts(lines: &[Line]) -> Vec<usize> {
+    (0..lines.len()).filter(|&i| lines[i].heading && (i == 0 || !lines[i - 1].heading)).collect()
+}
+
+// The `count`th of `starts` after `line`, or before it going backwards,
+// stopping at the last one there is
+pub fn jump(starts: &[usize], line: usize, count: usize, backward: bool) -> Option<usize> {
+    if backward {
+        starts.iter().rev().filter(|&&s| s < line).take(count).last().copied()
+    } else {
+        starts.iter().filter(|&&s| s > line).take(count).last().copied()
+    }
+}
+
+pub enum ScreenPosition {
+    Top,
+    Middle,
+    Bottom,
+}
+
+// The link to select for H, M or L among lines `top..top + height`: the
+// first link from the top, the one nearest the middle, or the last one from
+// the bottom. A count moves H and L that many lines inwards.
+pub fn screen_link(lines: &[Line], top: usize, height: usize, position: ScreenPosition, count: usize) -> Option<usize> {
+    let bottom = (top + height).min(lines.len());
+    // Each link once, on the first line it appears on
+    let mut links: Vec<(usize, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate().take(bottom).skip(top) {
+        for link in line.segments.iter().filter_map(|s| s.link) {
+            if !links.iter().any(|&(_, l)| l == link) {
+                links.push((i, link));
+            }
+        }
+    }
+
+    let offset = count.saturating_sub(1);
+    let found = match position {
+        ScreenPosition::Top => links.iter().find(|&&(line, _)| line >= top + offset),
+        ScreenPosition::Middle => {
+            let middle = top + (bottom - top) / 2;
+            links.iter().min_by_key(|&&(line, _)| line.abs_diff(middle))
+        }
+        ScreenPosition::Bottom => links.iter().rev().find(|&&(line, _)| line + offset < bottom),
+    };
+    found.map(|&(_, link)| link)
+}
+
+// Where each link appears on screen: one box per segment, as (link, line,
+// start column, end column)
+struct LinkBox {
+    link: usize,
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+fn link_boxes(lines: &[Line]) -> Vec<LinkBox> {
+    let mut boxes = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let mut column = 0;
+        for segment in &line.segments {
+            let width = segment.text.width();
+            if let Some(link) = segment.link {
+                boxes.push(LinkBox { link, line: i, start: column, end: column + width });
+            }
+            column += width;
+        }
+    }
+    boxes
+}
+
+// First line `link` appears on
+pub fn link_line(lines: &[Line], link: usize) -> Option<usize> {
+    lines.iter().position(|line| line.segments.iter().any(|s| s.link == Some(link)))
+}
+
+// Links with any part on lines `top..top + height`, top to bottom
+pub fn visible_links(lines: &[Line], top: usize, height: usize) -> Vec<usize> {
+    let mut links = Vec::new();
+    for line in lines.iter().skip(top).take(height) {
+        for link in line.segments.iter().filter_map(|s| s.link) {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LinkDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// The link nearest to `from` in `direction` on screen. Up and down prefer
+// the nearest line, then the nearest column; left and right stay on the
+// same line.
+pub fn link_towards(lines: &[Line], from: usize, direction: LinkDirection) -> Option<usize> {
+    let boxes = link_boxes(lines);
+    let own: Vec<&LinkBox> = boxes.iter().filter(|b| b.link == from).collect();
+    // Wrapped links are left from their first line and right from their last
+    let reference = match direction {
+        LinkDirection::Up | LinkDirection::Left => own.first()?,
+        LinkDirection::Down | LinkDirection::Right => own.last()?,
+    };
+    let gap = |b: &LinkBox| {
+        if b.end <= reference.start {
+            reference.start - b.end
+        } else {
+            b.start.saturating_sub(reference.end)
+        }
+    };
+
+    boxes
+        .iter()
+        .filter(|b| b.link != from)
+        .filter_map(|b| {
+            let distance = match direction {
+                LinkDirection::Down if b.line > reference.line => (b.line - reference.line, gap(b)),
+                LinkDirection::Up if b.line < reference.line => (reference.line - b.line, gap(b)),
+                LinkDirection::Right if b.line == reference.line && b.start >= reference.end => (0, gap(b)),
+                LinkDirection::Left if b.line == reference.line && b.end <= reference.start => (0, gap(b)),
+                _ => return None,
+            };
+            Some((distance, b.link))
+        })
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, link)| link)
+}
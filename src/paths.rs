@@ -0,0 +1,39 @@
+// Locations of connex's files, following the XDG base directory spec.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback),
+    };
+    Some(base.join("connex"))
+}
+
+// $XDG_CONFIG_HOME/connex, usually ~/.config/connex
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+// $XDG_DATA_HOME/connex, usually ~/.local/share/connex
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+// Where saved files go by default: $XDG_DOWNLOAD_DIR, or ~/Downloads
+pub fn download_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DOWNLOAD_DIR") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(PathBuf::from(env::var_os("HOME")?).join("Downloads")),
+    }
+}
+
+// A path typed at a prompt, with ~ standing for the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ if path == "~" => env::var_os("HOME").map_or_else(|| PathBuf::from(path), PathBuf::from),
+        _ => PathBuf::from(path),
+    }
+}
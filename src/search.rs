@@ -0,0 +1,245 @@
+// In-page find over the rendered lines of a page.
+
+use regex::{Regex, RegexBuilder};
+use tui::text::Span;
+
+use crate::config::Theme;
+use crate::layout::Line;
+
+// A match within one rendered line, as byte offsets into `Line::text()`
+pub struct Match {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Search {
+    pub query: String,
+    // Whether `?` started the search; `n` then moves backwards
+    pub backward: bool,
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub matches: Vec<Match>,
+    pub current: Option<usize>,
+    // Set when the query isn't a valid regex
+    pub error: Option<String>,
+}
+
+impl Search {
+    pub fn new(backward: bool, regex: bool, case_sensitive: bool) -> Self {
+        Search {
+            query: String::new(),
+            backward,
+            regex,
+            case_sensitive,
+            matches: Vec::new(),
+            current: None,
+            error: None,
+        }
+    }
+
+    fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex { self.query.clone() } else { regex::escape(&self.query) };
+        RegexBuilder::new(&pattern).case_insensitive(!self.case_sensitive).build()
+    }
+
+    // Recompute all matches for the current query
+    pub fn find_all(&mut self, lines: &[Line]) {
+        self.matches.clear();
+        self.current = None;
+        self.error = None;
+        if self.query.is_empty() {
+            return;
+        }
+
+        let re = match self.compile() {
+            Ok(re) => re,
+            Err(e) => {
+                // Regex errors quote the pattern first; their last line says what is
+                // wrong, and only that fits on the status line
+                self.error = Some(e.to_string().lines().last().unwrap_or("invalid regex").trim().to_string());
+                return;
+            }
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let text = line.text();
+            for m in re.find_iter(&text).filter(|m| !m.is_empty()) {
+                self.matches.push(Match { line: i, start: m.start(), end: m.end() });
+            }
+        }
+    }
+
+    // Select the first match at or after `line` (before it, searching
+    // backwards), wrapping around the page. Returns the matched line.
+    pub fn select_from(&mut self, line: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let index = if self.backward {
+            self.matches.iter().rposition(|m| m.line <= line).unwrap_or(self.matches.len() - 1)
+        } else {
+            self.matches.iter().position(|m| m.line >= line).unwrap_or(0)
+        };
+        self.current = Some(index);
+        Some(self.matches[index].line)
+    }
+
+    // Move to the next match in the search direction, or against it with
+    // `reverse` (`N`). Returns the matched line.
+    pub fn step(&mut self, reverse: bool) -> Option<usize> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+        let index = match self.current {
+            None => 0,
+            Some(i) if self.backward != reverse => (i + count - 1) % count,
+            Some(i) => (i + 1) % count,
+        };
+        self.current = Some(index);
+        Some(self.matches[index].line)
+    }
+
+    // Short description for the title bar, e.g. "/foo 3/17"
+    pub fn status(&self) -> String {
+        let prefix = if self.backward { '?' } else { '/' };
+        let mut flags = Vec::new();
+        if self.regex {
+            flags.push("regex");
+        }
+        if self.case_sensitive {
+            flags.push("case");
+        }
+        let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join(", ")) };
+
+        if let Some(error) = &self.error {
+            return format!("{}{}{}: {}", prefix, self.query, flags, error);
+        }
+        match self.current {
+            Some(i) => format!("{}{}{} {}/{}", prefix, self.query, flags, i + 1, self.matches.len()),
+            None if self.query.is_empty() => format!("{}{}", prefix, flags),
+            None => format!("{}{}{} no matches", prefix, self.query, flags),
+        }
+    }
+
+    // Restyle the spans of rendered line `line` so its matches stand out
+    pub fn highlight(&self, line: usize, spans: Vec<Span<'static>>, theme: &Theme) -> Vec<Span<'static>> {
+        let start = self.matches.partition_point(|m| m.line < line);
+        let end = self.matches.partition_point(|m| m.line <= line);
+        if start == end {
+            return spans;
+        }
+
+        let mut result = Vec::with_capacity(spans.len() + 2 * (end - start));
+        let mut offset = 0;
+        for span in spans {
+            let text = span.content.into_owned();
+            let span_end = offset + text.len();
+            let mut cursor = 0;
+
+            for (i, m) in self.matches[start..end].iter().enumerate() {
+                let lo = m.start.max(offset);
+                let hi = m.end.min(span_end);
+                if lo >= hi {
+                    continue;
+                }
+                let style = if self.current == Some(start + i) { theme.current_match } else { theme.search_match };
+                if lo - offset > cursor {
+                    result.push(Span::styled(text[cursor..lo - offset].to_string(), span.style));
+                }
+                result.push(Span::styled(text[lo - offset..hi - offset].to_string(), span.style.patch(style)));
+                cursor = hi - offset;
+            }
+            if cursor < text.len() {
+                result.push(Span::styled(text[cursor..].to_string(), span.style));
+            }
+            offset = span_end;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::style::Style;
+
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<Line> {
+        texts
+            .iter()
+            .map(|text| {
+                let mut line = Line::default();
+                line.push(text, Style::default(), None);
+                line
+            })
+            .collect()
+    }
+
+    fn search(query: &str, backward: bool, regex: bool, case_sensitive: bool, texts: &[&str]) -> Search {
+        let mut search = Search::new(backward, regex, case_sensitive);
+        search.query = query.to_string();
+        search.find_all(&lines(texts));
+        search
+    }
+
+    fn found(search: &Search) -> Vec<(usize, usize, usize)> {
+        search.matches.iter().map(|m| (m.line, m.start, m.end)).collect()
+    }
+
+    #[test]
+    fn plain_text_matches() {
+        let page = ["Foo bar foo", "nothing", "a.b FOO"];
+        assert_eq!(found(&search("foo", false, false, false, &page)), [(0, 0, 3), (0, 8, 11), (2, 4, 7)]);
+        assert_eq!(found(&search("foo", false, false, true, &page)), [(0, 8, 11)]);
+        // Regex syntax is taken literally unless asked for
+        assert_eq!(found(&search("a.b", false, false, false, &["axb a.b"])), [(0, 4, 7)]);
+        assert!(found(&search("", false, false, false, &page)).is_empty());
+    }
+
+    #[test]
+    fn regex_matches() {
+        assert_eq!(found(&search("a.b", false, true, false, &["axb a.b"])), [(0, 0, 3), (0, 4, 7)]);
+        assert_eq!(found(&search(r"\d+", false, true, false, &["12 ab 345"])), [(0, 0, 2), (0, 6, 9)]);
+        // Empty matches would never highlight anything
+        assert_eq!(found(&search("x*", false, true, false, &["ab xx"])), [(0, 3, 5)]);
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let search = search("(unclosed", false, true, false, &["(unclosed"]);
+        assert!(search.matches.is_empty());
+        let error = search.error.as_deref().unwrap();
+        assert!(!error.contains('\n') && !error.is_empty());
+        assert!(search.status().starts_with("/(unclosed (regex): "));
+    }
+
+    #[test]
+    fn stepping_wraps_around() {
+        let page = ["x", "", "x x", "", "x"];
+        let mut forward = search("x", false, false, false, &page);
+        assert_eq!(forward.select_from(1), Some(2));
+        assert_eq!(forward.status(), "/x 2/4");
+        assert_eq!(forward.step(false), Some(2));
+        assert_eq!(forward.step(false), Some(4));
+        assert_eq!(forward.step(false), Some(0));
+        assert_eq!(forward.step(true), Some(4));
+        assert_eq!(forward.select_from(5), Some(0));
+
+        let mut backward = search("x", true, false, false, &page);
+        assert_eq!(backward.select_from(3), Some(2));
+        assert_eq!(backward.status(), "?x 3/4");
+        assert_eq!(backward.step(false), Some(2));
+        assert_eq!(backward.step(false), Some(0));
+        assert_eq!(backward.step(false), Some(4));
+        assert_eq!(backward.step(true), Some(0));
+    }
+
+    #[test]
+    fn no_matches() {
+        let mut search = search("zzz", false, false, true, &["abc"]);
+        assert_eq!(search.select_from(0), None);
+        assert_eq!(search.step(false), None);
+        assert_eq!(search.status(), "/zzz (case) no matches");
+    }
+}
@@ -0,0 +1,99 @@
+// State shared by all tabs: configuration, bookmarks, history, cookies,
+// downloads and the about: pages built from them.
+
+use std::sync::Arc;
+
+use url::Url;
+
+use crate::bookmarks::Bookmarks;
+use crate::config::Config;
+use crate::cookies::CookieJar;
+use crate::downloads::Downloads;
+use crate::http::{HttpClient, Request};
+use crate::loader::Load;
+use crate::visits::Visits;
+
+// The page an about: link that changes something, such as deleting cookies
+// or cancelling a download, comes back to. The link runs once; only the page
+// goes in history, so Back and Reload can't run it again.
+pub fn action_page(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok().filter(|u| u.scheme() == "about")?;
+    let actions: &[&str] = match parsed.path() {
+        "cookies" => &["delete"],
+        "downloads" => &["cancel", "resume", "clear"],
+        _ => return None,
+    };
+    let acts = parsed.query_pairs().any(|(key, _)| actions.contains(&key.as_ref()));
+    acts.then(|| format!("about:{}", parsed.path()))
+}
+
+pub struct Session {
+    pub config: Config,
+    pub bookmarks: Bookmarks,
+    pub visits: Visits,
+    // Shared with the threads loading pages, and the client sending them
+    pub cookies: Arc<CookieJar>,
+    pub client: HttpClient,
+    pub downloads: Downloads,
+}
+
+impl Session {
+    // HTML for about: URLs, rendered through the same pipeline as any page
+    pub fn internal_page(&self, url: &str) -> Option<String> {
+        let parsed = Url::parse(url).ok().filter(|u| u.scheme() == "about")?;
+        let param = |name: &str| {
+            parsed.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+        };
+
+        Some(match parsed.path() {
+            "blank" => String::new(),
+            "bookmarks" => {
+                let query = param("q").unwrap_or_default();
+                self.bookmarks.to_html(&query, param("tag").as_deref(), &self.config.keymap)
+            }
+            "history" => self.visits.to_html(&param("q").unwrap_or_default(), &self.config.keymap),
+            "keys" => self.config.keymap.to_html(),
+            // Links on the page delete a domain's cookies, or one of them
+            "cookies" => {
+                let notice = param("delete").map(|domain| {
+                    let removed = self.cookies.remove(&domain, param("name").as_deref(), param("path").as_deref());
+                    match self.cookies.save() {
+                        Ok(()) => format!("Deleted {} cookie{} from {}", removed, if removed == 1 { "" } else { "s" }, domain),
+                        Err(e) => format!("Could not save cookies: {}", e),
+                    }
+                });
+                self.cookies.to_html(&param("q").unwrap_or_default(), notice.as_deref(), &self.config.keymap)
+            }
+            // Links on the page cancel, resume and clear downloads
+            "downloads" => {
+                let id = |name: &str| param(name).and_then(|id| id.parse::<usize>().ok());
+                let result = if let Some(id) = id("cancel") {
+                    Some(self.downloads.cancel(id))
+                } else if let Some(id) = id("resume") {
+                    Some(self.downloads.resume(id, &self.client))
+                } else if parsed.query_pairs().any(|(key, _)| key == "clear") {
+                    Some(Ok(self.downloads.clear()))
+                } else {
+                    None
+                };
+                let notice = result.map(|result| result.unwrap_or_else(|e| e));
+                self.downloads.to_html(notice.as_deref())
+            }
+            other => format!(
+                "<html><head><title>Not found</title></head><body><p>No such page: about:{}</p>\
+                 <p>Try <a href=\"about:bookmarks\">about:bookmarks</a> or \
+                 <a href=\"about:history\">about:history</a> or <a href=\"about:downloads\">about:downloads</a> or \
+                 <a href=\"about:cookies\">about:cookies</a> or <a href=\"about:keys\">about:keys</a>.</p></body></html>",
+                crate::document::escape_html(other)
+            ),
+        })
+    }
+
+    // Start loading a page; about: pages are ready immediately
+    pub fn load(&self, request: Request) -> Load {
+        match self.internal_page(&request.url) {
+            Some(html) => Load::ready(action_page(&request.url).unwrap_or(request.url), html),
+            None => Load::start(request, self.client.clone()),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// A browser tab: one page on screen plus its own history, scroll position
+// and in-flight load.
+
+use crate::content;
+use crate::document::Document;
+use crate::form;
+use crate::http::{DownloadOffer, Request, RequestBody, Response};
+use crate::history::History;
+use crate::layout::{layout, Line};
+use crate::loader::Load;
+use crate::motion;
+use crate::search::Search;
+use crate::session::{action_page, Session};
+
+// A navigation waiting for its page to load. History only moves once the
+// page arrives, so a cancelled load leaves everything as it was.
+pub enum Navigation {
+    Push(String),
+    // Like Push, for a link on the current page
+    Follow(String),
+    // Like Push, but in place of the current page rather than after it
+    Replace(String),
+    // Like Push, but POSTing a form to the URL
+    Post(String, RequestBody),
+    Back,
+    Forward,
+    Reload,
+}
+
+pub struct Tab {
+    pub history: History,
+    // URL of the page on screen, after redirects
+    pub url: String,
+    pub document: Document,
+    // The page as it arrived, for saving; None for error pages
+    pub response: Option<Response>,
+    // The document laid out with `layout_options`
+    pub lines: Vec<Line>,
+    // Width and whether links were numbered
+    layout_options: Option<(usize, bool)>,
+    pub selected_link_idx: Option<usize>,
+    pub scroll_offset: u16,
+    // Horizontal scroll, for preformatted text wider than the screen
+    pub hscroll_offset: u16,
+    pub search: Option<Search>,
+    pub loading: Option<(Navigation, Load)>,
+    // A file the last load turned up, waiting to be asked where to save it
+    pub download_offer: Option<DownloadOffer>,
+}
+
+impl Tab {
+    pub fn new(url: String, session: &Session) -> Self {
+        // An action link opened in a new tab runs once, like one followed
+        let page = action_page(&url).unwrap_or_else(|| url.clone());
+        let mut tab = Tab {
+            history: History::new(page.clone()),
+            url: page,
+            document: Document::from_text(""),
+            response: None,
+            lines: Vec::new(),
+            layout_options: None,
+            selected_link_idx: None,
+            scroll_offset: 0,
+            hscroll_offset: 0,
+            search: None,
+            loading: None,
+            download_offer: None,
+        };
+        tab.navigate(Navigation::Replace(url), session);
+        tab
+    }
+
+    // Start loading a page; it replaces any load still in flight
+    pub fn navigate(&mut self, navigation: Navigation, session: &Session) {
+        let url = match &navigation {
+            Navigation::Push(url) | Navigation::Follow(url) | Navigation::Replace(url) | Navigation::Post(url, _) => {
+                url.clone()
+            }
+            Navigation::Back => match self.history.peek_back() {
+                Some(entry) => entry.url.clone(),
+                None => return,
+            },
+            Navigation::Forward => match self.history.peek_forward() {
+                Some(entry) => entry.url.clone(),
+                None => return,
+            },
+            Navigation::Reload => self.history.current().url.clone(),
+        };
+        // Links and forms come from the page on screen, which matters for
+        // SameSite cookies; typed addresses and history have no initiator
+        let (body, initiator) = match &navigation {
+            Navigation::Post(_, body) => (Some(body.clone()), Some(self.url.clone())),
+            Navigation::Follow(_) => (None, Some(self.url.clone())),
+            _ => (None, None),
+        };
+        // An action link comes back to its page: in place of that page if it
+        // is on screen, as a new entry otherwise
+        let navigation = match (action_page(&url), navigation) {
+            (Some(page), Navigation::Replace(_)) => Navigation::Replace(page),
+            (Some(page), Navigation::Push(_) | Navigation::Follow(_)) => {
+                if self.url.split('?').next() == Some(page.as_str()) {
+                    Navigation::Replace(page)
+                } else {
+                    Navigation::Push(page)
+                }
+            }
+            (_, navigation) => navigation,
+        };
+        let load = session.load(Request { url, body, initiator });
+        if let Some((_, previous)) = self.loading.replace((navigation, load)) {
+            previous.cancel();
+        }
+    }
+
+    // Returns whether there was a load to cancel
+    pub fn cancel_loading(&mut self) -> bool {
+        match self.loading.take() {
+            Some((_, load)) => {
+                load.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Swap in the new page once it has loaded; back/forward restore the saved
+    // position. Returns the URL of a successfully loaded page.
+    pub fn poll_loading(&mut self, session: &Session) -> Option<String> {
+        let result = self.loading.as_mut()?.1.poll()?;
+        let (navigation, _) = self.loading.take().unwrap();
+        // Files are downloaded without leaving the page on screen
+        let result = match result {
+            Ok(Response { download: Some(offer), .. }) => {
+                self.download_offer = Some(offer);
+                return None;
+            }
+            result => result,
+        };
+
+        self.history.save_position(self.scroll_offset, self.selected_link_idx);
+        match navigation {
+            Navigation::Push(target) | Navigation::Follow(target) | Navigation::Post(target, _) => {
+                self.history.push(target)
+            }
+            Navigation::Replace(target) => self.history.replace(target),
+            Navigation::Back => {
+                self.history.back();
+            }
+            Navigation::Forward => {
+                self.history.forward();
+            }
+            Navigation::Reload => {}
+        }
+
+        let entry = self.history.current();
+        let parsed = result.and_then(|response| Ok((content::render(&response, &session.config.keymap)?, response)));
+        let loaded = match parsed {
+            Ok((document, response)) => {
+                self.url = response.url.clone();
+                self.document = document;
+                self.response = Some(response);
+                self.selected_link_idx = entry.selected_link_idx.filter(|&i| i < self.document.links.len());
+                self.scroll_offset = entry.scroll_offset;
+                Some(self.url.clone())
+            }
+            Err(e) => {
+                // Show an error message as the page if fetch fails
+                self.url = entry.url.clone();
+                self.document = Document::from_text(&format!("Error fetching URL: {}", e));
+                self.response = None;
+                self.selected_link_idx = None;
+                self.scroll_offset = 0;
+                None
+            }
+        };
+        self.layout_options = None;
+        self.hscroll_offset = 0;
+        loaded
+    }
+
+    // Re-flow the page whenever it changes, the terminal is resized or link
+    // numbers are turned on or off
+    pub fn relayout(&mut self, width: usize, number_links: bool) {
+        if self.layout_options == Some((width, number_links)) {
+            return;
+        }
+        self.lines = layout(&self.document, width, number_links);
+        self.layout_options = Some((width, number_links));
+        if let Some(search) = self.search.as_mut() {
+            search.find_all(&self.lines);
+            search.select_from(self.scroll_offset as usize);
+        }
+    }
+
+    // Change the page's form fields; the page is laid out again to show them
+    pub fn change_fields(&mut self, change: impl FnOnce(&mut Document)) {
+        change(&mut self.document);
+        self.layout_options = None;
+    }
+
+    // Submit the form `field` belongs to
+    pub fn submit_form(&mut self, field: usize, session: &Session) -> Result<(), String> {
+        let submission = form::submission(&self.document, field)?;
+        let navigation = match submission.body {
+            Some(body) => Navigation::Post(submission.url, body),
+            None => Navigation::Push(submission.url),
+        };
+        self.navigate(navigation, session);
+        Ok(())
+    }
+
+    // Scroll just enough to bring the selected link on screen
+    pub fn reveal_selection(&mut self, height: u16) {
+        let Some(line) = self.selected_link_idx.and_then(|link| motion::link_line(&self.lines, link)) else { return };
+        let line = u16::try_from(line).unwrap_or(u16::MAX);
+        if line < self.scroll_offset {
+            self.scroll_offset = line;
+        } else if line >= self.scroll_offset.saturating_add(height) {
+            self.scroll_offset = line.saturating_sub(height.saturating_sub(1));
+        }
+    }
+
+    // After scrolling, a selected link that went off screen gives way to the
+    // first link in view
+    pub fn keep_selection_visible(&mut self, height: u16) {
+        let max_scroll = self.lines.len().saturating_sub(height as usize);
+        self.scroll_offset = self.scroll_offset.min(u16::try_from(max_scroll).unwrap_or(u16::MAX));
+        let visible = motion::visible_links(&self.lines, self.scroll_offset as usize, height as usize);
+        if self.selected_link_idx.is_some_and(|link| !visible.contains(&link)) {
+            self.selected_link_idx = visible.first().copied();
+        }
+    }
+
+    // Short label for the tab bar: the page title, or the URL without its scheme
+    pub fn label(&self) -> String {
+        match &self.document.title {
+            Some(title) if self.loading.is_none() => title.clone(),
+            _ => {
+                let url = self.loading.as_ref().map_or(&self.url, |(_, load)| &load.url);
+                url.split_once("://").map_or(url.as_str(), |(_, rest)| rest).to_string()
+            }
+        }
+    }
+}
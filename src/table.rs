@@ -0,0 +1,471 @@
+// Table layout: sizes columns from their contents and draws the cells on a
+// character grid with box-drawing borders. Tables that can't fit the screen
+// even at their narrowest are stacked one cell after another instead.
+
+use tui::style::{Color, Modifier, Style};
+use unicode_width::UnicodeWidthChar;
+
+use crate::document::{Block, Run, Table, TableCell};
+use crate::layout::{content_widths, layout_blocks, Line, Segment};
+
+const BORDER_STYLE: Style = Style {
+    fg: Some(Color::DarkGray),
+    bg: None,
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::empty(),
+};
+
+// A cell positioned on the grid, after resolving row and column spans
+struct Placed<'a> {
+    cell: &'a TableCell,
+    row: usize,
+    col: usize,
+    rowspan: usize,
+    colspan: usize,
+}
+
+// Assign every cell its grid position, skipping slots taken by row spans
+// from earlier rows. Ragged rows are padded with empty slots.
+fn place_cells(table: &Table) -> (Vec<Placed<'_>>, usize) {
+    let rows = table.rows.len();
+    let mut occupied: Vec<Vec<bool>> = vec![Vec::new(); rows];
+    let mut placed = Vec::new();
+    let mut cols = 0;
+
+    for (r, row) in table.rows.iter().enumerate() {
+        let mut c = 0;
+        for cell in row {
+            while occupied[r].get(c).copied().unwrap_or(false) {
+                c += 1;
+            }
+            let rowspan = match cell.rowspan {
+                0 => rows - r,
+                n => n.min(rows - r),
+            };
+            for taken in occupied.iter_mut().skip(r).take(rowspan) {
+                if taken.len() < c + cell.colspan {
+                    taken.resize(c + cell.colspan, false);
+                }
+                taken[c..c + cell.colspan].iter_mut().for_each(|slot| *slot = true);
+            }
+            placed.push(Placed { cell, row: r, col: c, rowspan, colspan: cell.colspan });
+            c += cell.colspan;
+            cols = cols.max(c);
+        }
+    }
+
+    (placed, cols)
+}
+
+// Minimum and natural width of every column. Spanning cells only widen their
+// columns when the single-column cells haven't made room for them already.
+fn column_widths(placed: &[Placed], cols: usize, number_links: bool) -> (Vec<usize>, Vec<usize>) {
+    let mut min = vec![1; cols];
+    let mut max = vec![1; cols];
+
+    let mut spanning = Vec::new();
+    for p in placed {
+        let (cell_min, cell_max) = content_widths(&p.cell.blocks, number_links);
+        if p.colspan == 1 {
+            min[p.col] = min[p.col].max(cell_min);
+            max[p.col] = max[p.col].max(cell_max);
+        } else {
+            spanning.push((p.col, p.colspan, cell_min, cell_max));
+        }
+    }
+
+    for (col, colspan, cell_min, cell_max) in spanning {
+        for (widths, needed) in [(&mut min, cell_min), (&mut max, cell_max)] {
+            let range = &mut widths[col..col + colspan];
+            // Borders between the spanned columns count towards the cell
+            let available: usize = range.iter().sum::<usize>() + colspan - 1;
+            if needed > available {
+                let extra = needed - available;
+                for (i, w) in range.iter_mut().enumerate() {
+                    *w += extra / colspan + usize::from(i < extra % colspan);
+                }
+            }
+        }
+    }
+
+    for c in 0..cols {
+        max[c] = max[c].max(min[c]);
+    }
+    (min, max)
+}
+
+// Fit the columns into `available` columns of text, or None if they can't
+fn fit_columns(min: &[usize], max: &[usize], available: usize) -> Option<Vec<usize>> {
+    let min_total: usize = min.iter().sum();
+    let max_total: usize = max.iter().sum();
+    if min_total > available {
+        return None;
+    }
+    if max_total <= available {
+        return Some(max.to_vec());
+    }
+
+    // Share the spare room in proportion to how much each column wants
+    let spare = available - min_total;
+    let wanted = max_total - min_total;
+    let mut widths: Vec<usize> = min
+        .iter()
+        .zip(max)
+        .map(|(&lo, &hi)| lo + spare * (hi - lo) / wanted)
+        .collect();
+    let mut leftover = available - widths.iter().sum::<usize>();
+    for (w, &hi) in widths.iter_mut().zip(max) {
+        if leftover == 0 {
+            break;
+        }
+        if *w < hi {
+            *w += 1;
+            leftover -= 1;
+        }
+    }
+    Some(widths)
+}
+
+pub fn table_widths(table: &Table, number_links: bool) -> (usize, usize) {
+    let (placed, cols) = place_cells(table);
+    let (min, max) = column_widths(&placed, cols, number_links);
+    let borders = cols + 1;
+    (table.indent + min.iter().sum::<usize>() + borders, table.indent + max.iter().sum::<usize>() + borders)
+}
+
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+fn box_char(bits: u8) -> char {
+    match bits {
+        b if b == UP | DOWN | LEFT | RIGHT => '┼',
+        b if b == UP | DOWN | RIGHT => '├',
+        b if b == UP | DOWN | LEFT => '┤',
+        b if b == DOWN | LEFT | RIGHT => '┬',
+        b if b == UP | LEFT | RIGHT => '┴',
+        b if b == DOWN | RIGHT => '┌',
+        b if b == DOWN | LEFT => '┐',
+        b if b == UP | RIGHT => '└',
+        b if b == UP | LEFT => '┘',
+        b if b & (LEFT | RIGHT) != 0 && b & (UP | DOWN) == 0 => '─',
+        _ => '│',
+    }
+}
+
+#[derive(Clone, Default)]
+struct Glyph {
+    // Empty for the second column of a double-width character
+    text: String,
+    style: Style,
+    link: Option<usize>,
+}
+
+struct Canvas {
+    glyphs: Vec<Vec<Glyph>>,
+    borders: Vec<Vec<u8>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        let blank = Glyph { text: " ".to_string(), ..Glyph::default() };
+        Canvas { glyphs: vec![vec![blank; width]; height], borders: vec![vec![0; width]; height] }
+    }
+
+    fn draw_box(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for x in x0..=x1 {
+            for y in [y0, y1] {
+                if x > x0 {
+                    self.borders[y][x] |= LEFT;
+                }
+                if x < x1 {
+                    self.borders[y][x] |= RIGHT;
+                }
+            }
+        }
+        for y in y0..=y1 {
+            for x in [x0, x1] {
+                if y > y0 {
+                    self.borders[y][x] |= UP;
+                }
+                if y < y1 {
+                    self.borders[y][x] |= DOWN;
+                }
+            }
+        }
+    }
+
+    // Copy laid-out lines into the rectangle starting at (x, y), clipping to `width`
+    fn draw_lines(&mut self, lines: &[Line], x: usize, y: usize, width: usize) {
+        for (row, line) in self.glyphs[y..].iter_mut().zip(lines) {
+            let mut column = 0;
+            for segment in &line.segments {
+                for c in segment.text.chars() {
+                    let w = c.width().unwrap_or(0);
+                    if column + w > width {
+                        break;
+                    }
+                    if w == 0 {
+                        continue;
+                    }
+                    row[x + column] = Glyph { text: c.to_string(), style: segment.style, link: segment.link };
+                    if w == 2 {
+                        row[x + column + 1] = Glyph { text: String::new(), style: segment.style, link: segment.link };
+                    }
+                    column += w;
+                }
+            }
+        }
+    }
+
+    fn into_lines(self, indent: usize) -> Vec<Line> {
+        self.glyphs
+            .into_iter()
+            .zip(self.borders)
+            .map(|(glyphs, borders)| {
+                let mut line = Line::default();
+                if indent > 0 {
+                    line.segments.push(Segment { text: " ".repeat(indent), style: Style::default(), link: None });
+                }
+                for (glyph, bits) in glyphs.into_iter().zip(borders) {
+                    if bits != 0 {
+                        line.push(box_char(bits).encode_utf8(&mut [0; 4]), BORDER_STYLE, None);
+                    } else {
+                        line.push(&glyph.text, glyph.style, glyph.link);
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+pub fn layout_table(table: &Table, width: usize, number_links: bool, lines: &mut Vec<Line>) {
+    let (placed, cols) = place_cells(table);
+    if cols == 0 {
+        return;
+    }
+    let indent = table.indent.min(width / 2);
+    let (min, max) = column_widths(&placed, cols, number_links);
+    let available = width.saturating_sub(indent + cols + 1);
+    let Some(widths) = fit_columns(&min, &max, available) else {
+        layout_stacked(table, &placed, width, number_links, lines);
+        return;
+    };
+
+    // Left border of every column, plus the right edge of the table
+    let mut xs = vec![0];
+    for w in &widths {
+        xs.push(xs.last().unwrap() + w + 1);
+    }
+
+    // Lay out the cells, then grow rows until every cell fits
+    let rows = table.rows.len();
+    let mut heights = vec![1; rows];
+    let mut contents = Vec::with_capacity(placed.len());
+    for p in &placed {
+        let cell_width = xs[p.col + p.colspan] - xs[p.col] - 1;
+        let mut cell_lines = Vec::new();
+        layout_blocks(&p.cell.blocks, cell_width, number_links, &mut cell_lines);
+        if p.rowspan == 1 {
+            heights[p.row] = heights[p.row].max(cell_lines.len());
+        }
+        contents.push(cell_lines);
+    }
+    for (p, cell_lines) in placed.iter().zip(&contents) {
+        let spanned = &heights[p.row..p.row + p.rowspan];
+        let available = spanned.iter().sum::<usize>() + p.rowspan - 1;
+        if cell_lines.len() > available {
+            heights[p.row + p.rowspan - 1] += cell_lines.len() - available;
+        }
+    }
+
+    let mut ys = vec![0];
+    for h in &heights {
+        ys.push(ys.last().unwrap() + h + 1);
+    }
+
+    let mut canvas = Canvas::new(xs[cols] + 1, ys[rows] + 1);
+    for (p, cell_lines) in placed.iter().zip(&contents) {
+        let (x0, x1) = (xs[p.col], xs[p.col + p.colspan]);
+        let (y0, y1) = (ys[p.row], ys[p.row + p.rowspan]);
+        canvas.draw_box(x0, y0, x1, y1);
+        canvas.draw_lines(cell_lines, x0 + 1, y0 + 1, x1 - x0 - 1);
+    }
+    // Slots no cell reached still get borders so the grid stays closed
+    let mut covered = vec![vec![false; cols]; rows];
+    for p in &placed {
+        for row in covered.iter_mut().skip(p.row).take(p.rowspan) {
+            row[p.col..p.col + p.colspan].iter_mut().for_each(|slot| *slot = true);
+        }
+    }
+    for (r, row) in covered.iter().enumerate() {
+        for (c, &slot) in row.iter().enumerate() {
+            if !slot {
+                canvas.draw_box(xs[c], ys[r], xs[c + 1], ys[r + 1]);
+            }
+        }
+    }
+
+    lines.extend(canvas.into_lines(indent));
+}
+
+fn plain_text(blocks: &[Block]) -> String {
+    let mut text = String::new();
+    for block in blocks {
+        let runs: Vec<&Run> = match block {
+            Block::Paragraph { runs, .. } => runs.iter().collect(),
+            Block::Preformatted { lines, .. } => lines.iter().flatten().collect(),
+            Block::Table(_) | Block::Blank => continue,
+        };
+        for run in runs {
+            text.push_str(&run.text);
+        }
+        text.push(' ');
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Narrow-screen fallback: one cell after another, labelled with the column
+// header when the table starts with a header row
+fn layout_stacked(table: &Table, placed: &[Placed], width: usize, number_links: bool, lines: &mut Vec<Line>) {
+    let indent = table.indent.min(width / 2);
+    let pad = |line: &mut Line| {
+        if indent > 0 {
+            line.segments.insert(0, Segment { text: " ".repeat(indent), style: Style::default(), link: None });
+        }
+    };
+
+    let header_row = table.rows.first().filter(|row| row.iter().all(|cell| cell.header));
+    let labels: Vec<String> = header_row
+        .map(|row| {
+            row.iter()
+                .flat_map(|cell| std::iter::repeat_n(plain_text(&cell.blocks), cell.colspan))
+                .collect()
+        })
+        .unwrap_or_default();
+    let first_row = usize::from(header_row.is_some());
+
+    let label_style = Style::default().add_modifier(Modifier::BOLD);
+    let rule_width = width.saturating_sub(indent).min(40);
+    let mut last_row = None;
+    for p in placed.iter().filter(|p| p.row >= first_row) {
+        if last_row.is_some_and(|r| r != p.row) {
+            let mut rule = Line::default();
+            rule.push(&"─".repeat(rule_width), BORDER_STYLE, None);
+            pad(&mut rule);
+            lines.push(rule);
+        }
+        last_row = Some(p.row);
+
+        let cell = p.cell;
+        let first = lines.len();
+        match labels.get(p.col).filter(|label| !label.is_empty() && !cell.header) {
+            Some(label) => {
+                let mut heading = Line::default();
+                heading.push(&format!("{}:", label), label_style, None);
+                lines.push(heading);
+                let content_start = lines.len();
+                layout_blocks(&cell.blocks, width.saturating_sub(indent + 2), number_links, lines);
+                for line in &mut lines[content_start..] {
+                    line.segments.insert(0, Segment { text: "  ".to_string(), style: Style::default(), link: None });
+                }
+            }
+            None => layout_blocks(&cell.blocks, width.saturating_sub(indent), number_links, lines),
+        }
+        for line in &mut lines[first..] {
+            pad(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(text: &str, colspan: usize, rowspan: usize) -> TableCell {
+        let runs = vec![Run { text: text.to_string(), style: Style::default(), link: None }];
+        let blocks = vec![Block::Paragraph { runs, indent: 0, marker: None, heading: false }];
+        TableCell { blocks, header: false, colspan, rowspan }
+    }
+
+    // Every placed cell as "text row,col rowspan x colspan", and the number
+    // of columns
+    fn positions(table: &Table) -> (Vec<String>, usize) {
+        let (placed, cols) = place_cells(table);
+        let positions = placed
+            .iter()
+            .map(|p| format!("{} {},{} {}x{}", plain_text(&p.cell.blocks), p.row, p.col, p.rowspan, p.colspan))
+            .collect();
+        (positions, cols)
+    }
+
+    #[test]
+    fn spans_take_their_slots() {
+        let table = Table {
+            rows: vec![
+                vec![cell("a", 1, 2), cell("b", 2, 1)],
+                vec![cell("c", 1, 1), cell("d", 1, 1)],
+                vec![cell("e", 3, 1)],
+            ],
+            indent: 0,
+        };
+        let (placed, cols) = positions(&table);
+        assert_eq!(placed, ["a 0,0 2x1", "b 0,1 1x2", "c 1,1 1x1", "d 1,2 1x1", "e 2,0 1x3"]);
+        assert_eq!(cols, 3);
+    }
+
+    #[test]
+    fn rowspans_stop_at_the_last_row() {
+        // rowspan="0" runs to the end, and longer spans are cut short there
+        let table = Table {
+            rows: vec![vec![cell("a", 1, 0), cell("b", 1, 5)], vec![cell("c", 1, 1)], vec![cell("d", 1, 1)]],
+            indent: 0,
+        };
+        let (placed, cols) = positions(&table);
+        assert_eq!(placed, ["a 0,0 3x1", "b 0,1 3x1", "c 1,2 1x1", "d 2,2 1x1"]);
+        assert_eq!(cols, 3);
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let table = Table {
+            rows: vec![vec![cell("a", 1, 1), cell("b", 1, 1), cell("c", 1, 1)], vec![cell("d", 1, 1)]],
+            indent: 0,
+        };
+        let (placed, cols) = positions(&table);
+        assert_eq!(placed[3], "d 1,0 1x1");
+        assert_eq!(cols, 3);
+    }
+
+    #[test]
+    fn fitting_columns() {
+        assert_eq!(fit_columns(&[2, 2], &[5, 4], 20), Some(vec![5, 4]));
+        assert_eq!(fit_columns(&[2, 2], &[5, 4], 9), Some(vec![5, 4]));
+        assert_eq!(fit_columns(&[2, 2], &[5, 4], 3), None);
+        assert_eq!(fit_columns(&[2, 2], &[5, 4], 4), Some(vec![2, 2]));
+        // The spare room goes to the column that wants more
+        assert_eq!(fit_columns(&[2, 2], &[10, 4], 8), Some(vec![6, 2]));
+        let widths = fit_columns(&[1, 1, 1], &[7, 7, 7], 10).unwrap();
+        assert_eq!(widths.iter().sum::<usize>(), 10);
+        assert!(widths.iter().all(|&w| (1..=7).contains(&w)));
+    }
+
+    #[test]
+    fn spanning_cells_widen_their_columns() {
+        let table = Table {
+            rows: vec![vec![cell("a", 1, 1), cell("b", 1, 1)], vec![cell("wide cell", 2, 1)]],
+            indent: 0,
+        };
+        let (placed, cols) = place_cells(&table);
+        let (_, max) = column_widths(&placed, cols, false);
+        // Nine columns of text, counting the border between the two
+        assert_eq!(max.iter().sum::<usize>() + 1, 9);
+
+        let mut lines = Vec::new();
+        layout_table(&table, 40, false, &mut lines);
+        let text: Vec<String> = lines.iter().map(Line::text).collect();
+        assert_eq!(text, ["┌────┬────┐", "│a   │b   │", "├────┴────┤", "│wide cell│", "└─────────┘"]);
+    }
+}
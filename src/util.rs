@@ -0,0 +1,14 @@
+// Small helpers shared by the modules that keep files of their own.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// A field for a tab-separated file, where tabs and newlines would break the
+// format
+pub fn clean(field: &str) -> String {
+    field.split(['\t', '\n', '\r']).collect::<Vec<_>>().join(" ").trim().to_string()
+}
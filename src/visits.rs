@@ -0,0 +1,156 @@
+// Every page visited, kept across sessions in a tab-separated log under the
+// XDG data directory and listed on the about:history page.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{Local, TimeZone};
+
+use crate::document::escape_html;
+use crate::keys::{Action, Keymap};
+use crate::paths;
+use crate::util::{clean, now};
+
+pub const PAGE_URL: &str = "about:history";
+
+// The most entries the history page lists at once
+const PAGE_LIMIT: usize = 1000;
+
+// The history page filtered by `query`
+pub fn page_url(query: &str) -> String {
+    if query.trim().is_empty() {
+        return PAGE_URL.to_string();
+    }
+    let encoded: String = url::form_urlencoded::byte_serialize(query.trim().as_bytes()).collect();
+    format!("{}?q={}", PAGE_URL, encoded)
+}
+
+pub struct Visit {
+    // Seconds since the Unix epoch
+    pub time: u64,
+    pub url: String,
+    pub title: String,
+}
+
+impl Visit {
+    fn matches(&self, query: &str) -> bool {
+        let haystack = format!("{} {}", self.title, self.url).to_lowercase();
+        query.split_whitespace().all(|word| haystack.contains(&word.to_lowercase()))
+    }
+}
+
+#[derive(Default)]
+pub struct Visits {
+    // Oldest first, in the order they were logged
+    pub entries: Vec<Visit>,
+    // Where visits are appended; None keeps them in memory only
+    path: Option<PathBuf>,
+}
+
+impl Visits {
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("history.tsv"))
+    }
+
+    // Load the log from `path`; a missing file is an empty history
+    pub fn load(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut visits = Visits { entries: Vec::new(), path };
+        let contents = match &visits.path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(visits),
+                Err(e) => return Err(e),
+            },
+            None => return Ok(visits),
+        };
+
+        for line in contents.lines() {
+            let mut fields = line.split('\t');
+            let Some(time) = fields.next().and_then(|t| t.parse().ok()) else { continue };
+            let Some(url) = fields.next().filter(|u| !u.is_empty()) else { continue };
+            visits.entries.push(Visit {
+                time,
+                url: url.to_string(),
+                title: fields.next().unwrap_or("").to_string(),
+            });
+        }
+        Ok(visits)
+    }
+
+    // Log a visit, appending it to the file straight away so nothing is lost
+    // if connex is killed
+    pub fn record(&mut self, url: &str, title: Option<&str>) -> io::Result<()> {
+        let visit = Visit { time: now(), url: clean(url), title: clean(title.unwrap_or("")) };
+        let line = format!("{}\t{}\t{}\n", visit.time, visit.url, visit.title);
+        self.entries.push(visit);
+
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+    }
+
+    // Distinct URLs, least recently visited first, for address bar completion
+    pub fn recent_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for visit in self.entries.iter().rev() {
+            if !urls.contains(&visit.url) {
+                urls.push(visit.url.clone());
+            }
+        }
+        urls.reverse();
+        urls
+    }
+
+    // The about:history page: newest first, grouped by day, optionally
+    // filtered by words in the title or URL
+    pub fn to_html(&self, query: &str, keymap: &Keymap) -> String {
+        let mut html = String::from("<html><head><title>History</title></head><body><h1>History</h1>");
+        if !query.is_empty() {
+            html.push_str(&format!(
+                "<p>Showing pages matching <b>{}</b>. <a href=\"{}\">Show all</a></p>",
+                escape_html(query),
+                PAGE_URL
+            ));
+        }
+
+        let shown: Vec<&Visit> = self.entries.iter().rev().filter(|v| v.matches(query)).take(PAGE_LIMIT + 1).collect();
+        if shown.is_empty() {
+            html.push_str("<p>No pages.</p>");
+        }
+
+        let mut day = None;
+        for visit in shown.iter().take(PAGE_LIMIT) {
+            let Some(time) = Local.timestamp_opt(visit.time as i64, 0).single() else { continue };
+            if day != Some(time.date_naive()) {
+                if day.is_some() {
+                    html.push_str("</ul>");
+                }
+                day = Some(time.date_naive());
+                html.push_str(&format!("<h2>{}</h2><ul>", time.format("%A, %-d %B %Y")));
+            }
+            let title = if visit.title.is_empty() { &visit.url } else { &visit.title };
+            html.push_str(&format!(
+                "<li>{} <a href=\"{}\">{}</a> <code>{}</code></li>",
+                time.format("%H:%M"),
+                escape_html(&visit.url),
+                escape_html(title),
+                escape_html(&visit.url)
+            ));
+        }
+        if day.is_some() {
+            html.push_str("</ul>");
+        }
+        if shown.len() > PAGE_LIMIT {
+            html.push_str(&format!("<p>Only the latest {} pages are shown; filter to find older ones.</p>", PAGE_LIMIT));
+        }
+
+        html.push_str(&format!(
+            "<p><small>{}: filter as you type</small></p></body></html>",
+            escape_html(&keymap.keys_for(Action::Filter))
+        ));
+        html
+    }
+}